@@ -43,7 +43,7 @@ fn pretty_print_gameboy(gameboy: &GameBoy) -> Result<(), io::Error> {
     let stdout = std::io::stdout();
     let mut lock = stdout.lock();
 
-    writeln!(lock, "")?;
+    writeln!(lock)?;
 
     writeln!(lock, "{}", "Registers".bold().red())?;
     writeln!(
@@ -126,7 +126,7 @@ fn main() {
     }
 
     let rom_path = args.last().unwrap();
-    let mut gameboy = GameBoy::new(&rom_path).unwrap();
+    let mut gameboy = GameBoy::new(rom_path).unwrap();
 
     // Input
     #[rustfmt::skip]
@@ -160,17 +160,9 @@ fn main() {
 
         // Stopping the emulator
         match input {
-            "1" => {
-                if gameboy.registers.pc == additional_input {
-                    exit(0);
-                }
-            }
-
-            "2" => {
-                if gameboy.bus.read(gameboy.registers.pc) == additional_input as u8 {
-                    exit(0);
-                }
-            }
+            "1" if gameboy.registers.pc == additional_input => exit(0),
+
+            "2" if gameboy.bus.read(gameboy.registers.pc) == additional_input as u8 => exit(0),
 
             _ => {}
         }
@@ -71,7 +71,7 @@ fn run_test(schemas: Vec<JsonSchema>) {
         print!("Running test {}...", schema.name.yellow());
 
         // Creating gameboy
-        let mut gameboy = GameBoy::new_from_rom_array(vec![]);
+        let mut gameboy = GameBoy::new_from_rom_array_unchecked(vec![]);
 
         // IO must be all zero during tests
         gameboy.bus.io = [0; IO_SIZE];
@@ -80,7 +80,7 @@ fn run_test(schemas: Vec<JsonSchema>) {
         for (address, value) in &schema.initial.ram {
             // We need to use the `direct_rom_write` function if writing to rom, the json
             // tests do that a lot
-            match *address < 0x8000 as u16 {
+            match *address < 0x8000 {
                 false => gameboy.bus.write(*address, *value),
                 true => gameboy.bus.mbc.direct_rom_write(*address, *value),
             }
@@ -100,20 +100,20 @@ fn run_test(schemas: Vec<JsonSchema>) {
         let final_flags = register_schema_to_flags(&schema.final_);
 
         if gameboy.registers == final_registers {
-            print!("{}", format!(" ✔ Registers are correct ").green());
+            print!("{}", " ✔ Registers are correct ".green());
         } else {
-            print!("{}", format!(" ✘ Registers are not correct ").red());
+            print!("{}", " ✘ Registers are not correct ".red());
             were_registers_wrong = true;
         }
 
         if gameboy.flags == final_flags {
-            print!("{}", format!("✔ Flags are correct").green());
+            print!("{}", "✔ Flags are correct".green());
         } else {
-            print!("{}", format!("✘ Flags are not correct").red());
+            print!("{}", "✘ Flags are not correct".red());
             were_flags_wrong = true;
         }
 
-        println!("");
+        println!();
 
         if were_flags_wrong || were_registers_wrong {
             println!("\n");
@@ -148,7 +148,7 @@ fn run_test(schemas: Vec<JsonSchema>) {
                 format!("{:x}", schema.final_.pc).bold().blue(),
             );
 
-            println!("");
+            println!();
         }
 
         if were_flags_wrong {
@@ -170,7 +170,7 @@ fn run_test(schemas: Vec<JsonSchema>) {
                 bool_to_symbol(final_flags.half_carry),
             );
 
-            println!("");
+            println!();
         }
 
         if were_registers_wrong || were_flags_wrong {
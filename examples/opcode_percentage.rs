@@ -5,7 +5,7 @@ use colored::Colorize;
 use gameman::GameBoy;
 
 fn main() {
-    let mut gb = GameBoy::new_from_rom_array(vec![]);
+    let mut gb = GameBoy::new_from_rom_array_unchecked(vec![]);
 
     let mut working_opcodes = 0;
     let mut total_opcodes = 0;
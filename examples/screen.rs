@@ -1,6 +1,6 @@
 use gameman::{
     consts::display::{DISPLAY_SIZE_X, DISPLAY_SIZE_Y},
-    gpu::Color,
+    palette::Palette,
     GameBoy,
 };
 use macroquad::{
@@ -24,7 +24,7 @@ async fn main() {
     }
 
     let rom_path = args.last().unwrap();
-    let mut gameboy = GameBoy::new(&rom_path).unwrap();
+    let mut gameboy = GameBoy::new(rom_path).unwrap();
 
     let mut image = Image::gen_image_color(DISPLAY_SIZE_X as u16, DISPLAY_SIZE_Y as u16, WHITE);
     let texture = Texture2D::from_image(&image);
@@ -52,20 +52,9 @@ async fn main() {
         gameboy.joypad.is_a_pressed = is_key_down(KeyCode::O);
         gameboy.joypad.is_b_pressed = is_key_down(KeyCode::P);
 
-        for (y_coordinate, y) in gameboy.gpu.screen.iter_mut().enumerate() {
-            for (x_coordinate, x) in y.iter().enumerate() {
-                image.set_pixel(
-                    x_coordinate as u32,
-                    y_coordinate as u32,
-                    match x {
-                        Color::Dark => MacroColor::from_rgba(0x14, 0x2C, 0x38, 255),
-                        Color::MediumlyDark => MacroColor::from_rgba(0x54, 0x8C, 0x70, 255),
-                        Color::MediumlyLight => MacroColor::from_rgba(0xAC, 0xD4, 0x90, 255),
-                        Color::Light => MacroColor::from_rgba(0xE8, 0xFC, 0xCC, 255),
-                    },
-                );
-            }
-        }
+        image
+            .bytes
+            .copy_from_slice(&gameboy.screen_rgba8(&Palette::CLASSIC_GREEN));
 
         texture.update(&image);
         draw_texture_ex(
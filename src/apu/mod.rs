@@ -0,0 +1,477 @@
+//! The APU (Audio Processing Unit) has four channels, two square waves, one custom wave
+//! and one noise channel. Every channel produces a digital value from 0 to 15, these get
+//! converted to analog, mixed together and then sampled at the rate the frontend wants.
+//!
+//! This is a really good resource: https://gbdev.io/pandocs/Audio_details.html
+
+mod noise;
+mod square;
+mod wave;
+
+use std::collections::VecDeque;
+
+use noise::NoiseChannel;
+use square::SquareChannel;
+use wave::WaveChannel;
+
+use crate::{
+    bus::Bus,
+    common::Bit,
+    consts::{
+        apu::{NR50, NR51, NR52},
+        bus::IO_START,
+        cpu::CLOCK_SPEED,
+    },
+    save_state::{SaveState, SaveStateError, StateReader, StateWriter},
+};
+
+/// The rate at which samples are generated if the frontend does not specify one
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+/// The frame sequencer runs at 512hz, so it steps every 8192 dots
+const FRAME_SEQUENCER_TICKS: u16 = 8192;
+
+/// The frontend may not pull samples for a while (for example when it's paused), so we
+/// only keep half a second of audio around and throw away the oldest samples
+const MAX_BUFFERED_SECONDS_DIVIDER: u32 = 2;
+
+/// Every channel is different, but the APU talks to all of them in the same way
+#[allow(unused_variables)]
+pub(crate) trait Channel: Send + SaveState {
+    /// Called when the game writes to NRx4 with bit 7 set
+    fn trigger(&mut self, bus: &Bus);
+
+    /// Called when the game writes to the length register NRx1
+    fn reload_length(&mut self, bus: &Bus);
+
+    /// Called every dot, this is where the frequency timer lives
+    fn tick(&mut self, bus: &Bus);
+
+    /// The digital output of the channel, from 0 to 15
+    fn output(&self, bus: &Bus) -> u8;
+
+    fn is_enabled(&self) -> bool;
+    fn is_dac_enabled(&self, bus: &Bus) -> bool;
+    fn disable(&mut self);
+
+    // Frame sequencer events
+    fn clock_length(&mut self, bus: &Bus);
+    fn clock_envelope(&mut self) {}
+    fn clock_sweep(&mut self, bus: &mut Bus) {}
+}
+
+pub(crate) type Channels = [Box<dyn Channel>; 4];
+
+pub struct Apu {
+    channels: Channels,
+
+    /// The frame sequencer clocks the length timers, the envelopes and the sweep. It has
+    /// 8 steps, this is the one that will be executed next
+    frame_sequencer_step: u8,
+    frame_sequencer_ticks: u16,
+
+    /// The output rate of the samples in hertz
+    sample_rate: u32,
+
+    /// We add `sample_rate` to this every dot, and when it goes over the clock speed of
+    /// the GameBoy it's time to produce a new sample
+    sample_counter: u32,
+
+    /// The left and right samples that the frontend has yet to pull
+    samples: VecDeque<(f32, f32)>,
+
+    /// The GameBoy has a capacitor on each output that removes the DC offset from the
+    /// signal, these are their charges
+    capacitor_left: f32,
+    capacitor_right: f32,
+
+    /// How much the capacitors keep their charge every sample, it depends on the sample
+    /// rate so it's calculated when that changes
+    charge_factor: f32,
+}
+
+impl Apu {
+    pub(crate) fn new() -> Self {
+        Self {
+            channels: [
+                Box::new(SquareChannel::new(0, true)),
+                Box::new(SquareChannel::new(1, false)),
+                Box::new(WaveChannel::new()),
+                Box::new(NoiseChannel::new()),
+            ],
+            frame_sequencer_step: 0,
+            frame_sequencer_ticks: 0,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            sample_counter: 0,
+            samples: VecDeque::new(),
+            capacitor_left: 0.0,
+            capacitor_right: 0.0,
+            charge_factor: get_charge_factor(DEFAULT_SAMPLE_RATE),
+        }
+    }
+
+    /// Changes the rate at which samples are produced, the samples already generated are
+    /// thrown away since they were made for the old rate
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate.clamp(1, CLOCK_SPEED);
+        self.charge_factor = get_charge_factor(self.sample_rate);
+        self.sample_counter = 0;
+        self.samples.clear();
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// The number of stereo samples that can be pulled right now
+    pub fn available_samples(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Moves as many samples as possible into `buffer`, interleaved as left and right, and
+    /// returns the number of stereo samples that were written. The samples go from -1.0
+    /// to 1.0
+    pub fn pull_samples(&mut self, buffer: &mut [f32]) -> usize {
+        let mut written = 0;
+
+        for frame in buffer.chunks_exact_mut(2) {
+            let Some((left, right)) = self.samples.pop_front() else {
+                break;
+            };
+
+            frame[0] = left;
+            frame[1] = right;
+            written += 1;
+        }
+
+        written
+    }
+}
+
+impl Apu {
+    /// Just like `Gpu::tick`, this needs to be called once every dot
+    pub(crate) fn tick(&mut self, bus: &mut Bus) {
+        let is_powered_on = get_register(bus, NR52).get_bit(7);
+
+        if is_powered_on {
+            self.handle_register_writes(bus);
+            self.tick_frame_sequencer(bus);
+
+            for channel in self.channels.iter_mut() {
+                channel.tick(bus);
+            }
+        } else {
+            // When the APU is turned off, every channel is turned off and the frame
+            // sequencer starts from the beginning once it's turned back on
+            self.channels
+                .iter_mut()
+                .for_each(|channel| channel.disable());
+            self.frame_sequencer_step = 0;
+            self.frame_sequencer_ticks = 0;
+            bus.needs_to_trigger_channel = [false; 4];
+            bus.needs_to_reload_length = [false; 4];
+        }
+
+        self.update_nr52(bus);
+        self.generate_sample(bus);
+    }
+
+    /// The bus can't talk to the channels directly, so it leaves us a note whenever a
+    /// register that starts something gets written
+    fn handle_register_writes(&mut self, bus: &mut Bus) {
+        for (i, channel) in self.channels.iter_mut().enumerate() {
+            if bus.needs_to_reload_length[i] {
+                channel.reload_length(bus);
+                bus.needs_to_reload_length[i] = false;
+            }
+
+            if bus.needs_to_trigger_channel[i] {
+                channel.trigger(bus);
+                bus.needs_to_trigger_channel[i] = false;
+            }
+        }
+
+        // A channel whose DAC is turned off is turned off as well
+        for channel in self.channels.iter_mut() {
+            if !channel.is_dac_enabled(bus) {
+                channel.disable();
+            }
+        }
+    }
+
+    /// Step 0, 2, 4 and 6 clock the length timers, step 2 and 6 clock the sweep and step
+    /// 7 clocks the envelopes
+    fn tick_frame_sequencer(&mut self, bus: &mut Bus) {
+        self.frame_sequencer_ticks += 1;
+
+        if self.frame_sequencer_ticks < FRAME_SEQUENCER_TICKS {
+            return;
+        }
+
+        self.frame_sequencer_ticks = 0;
+
+        for channel in self.channels.iter_mut() {
+            match self.frame_sequencer_step {
+                0 | 4 => channel.clock_length(bus),
+                2 | 6 => {
+                    channel.clock_length(bus);
+                    channel.clock_sweep(bus);
+                }
+                7 => channel.clock_envelope(),
+                _ => {}
+            }
+        }
+
+        self.frame_sequencer_step = (self.frame_sequencer_step + 1) % 8;
+    }
+
+    /// The lower nibble of NR52 is read only and tells the game which channels are on
+    fn update_nr52(&self, bus: &mut Bus) {
+        let nr52 = &mut bus.io[(NR52 - IO_START as u16) as usize];
+
+        for (i, channel) in self.channels.iter().enumerate() {
+            nr52.set_bit(i as u8, channel.is_enabled());
+        }
+    }
+
+    fn generate_sample(&mut self, bus: &Bus) {
+        self.sample_counter += self.sample_rate;
+
+        if self.sample_counter < CLOCK_SPEED {
+            return;
+        }
+
+        self.sample_counter -= CLOCK_SPEED;
+
+        let (left, right) = self.mix(bus);
+        let left = self.high_pass(left, true);
+        let right = self.high_pass(right, false);
+
+        if self.samples.len() >= (self.sample_rate / MAX_BUFFERED_SECONDS_DIVIDER) as usize {
+            self.samples.pop_front();
+        }
+
+        self.samples.push_back((left, right));
+    }
+
+    /// NR51 selects on which side each channel is played, and NR50 sets the volume of each
+    /// side
+    fn mix(&self, bus: &Bus) -> (f32, f32) {
+        let nr50 = get_register(bus, NR50);
+        let nr51 = get_register(bus, NR51);
+
+        let mut left = 0.0;
+        let mut right = 0.0;
+
+        for (i, channel) in self.channels.iter().enumerate() {
+            // The DAC converts the digital 0 to 15 to an analog -1.0 to 1.0, if it's off
+            // it outputs nothing at all
+            if !channel.is_dac_enabled(bus) {
+                continue;
+            }
+
+            let digital = match channel.is_enabled() {
+                true => channel.output(bus),
+                false => 0,
+            };
+
+            let analog = 1.0 - digital as f32 / 7.5;
+
+            if nr51.get_bit(i as u8 + 4) {
+                left += analog;
+            }
+
+            if nr51.get_bit(i as u8) {
+                right += analog;
+            }
+        }
+
+        let left_volume = ((nr50 >> 4) & 0b111) as f32 + 1.0;
+        let right_volume = (nr50 & 0b111) as f32 + 1.0;
+
+        (
+            left / 4.0 * left_volume / 8.0,
+            right / 4.0 * right_volume / 8.0,
+        )
+    }
+
+    fn high_pass(&mut self, input: f32, is_left: bool) -> f32 {
+        let charge_factor = self.charge_factor;
+        let capacitor = match is_left {
+            true => &mut self.capacitor_left,
+            false => &mut self.capacitor_right,
+        };
+
+        let output = input - *capacitor;
+        *capacitor = input - output * charge_factor;
+
+        output
+    }
+}
+
+/// How much the capacitor keeps its charge every dot, to the power of the number of dots
+/// in a sample
+fn get_charge_factor(sample_rate: u32) -> f32 {
+    0.999958f32.powf(CLOCK_SPEED as f32 / sample_rate as f32)
+}
+
+/// The samples that have yet to be pulled and the sample rate are not saved, they belong
+/// to the frontend more than to the GameBoy
+impl SaveState for Apu {
+    fn save_state(&self, writer: &mut StateWriter) {
+        for channel in &self.channels {
+            channel.save_state(writer);
+        }
+
+        writer.write_u8(self.frame_sequencer_step);
+        writer.write_u16(self.frame_sequencer_ticks);
+        writer.write_u32(self.sample_counter);
+        writer.write_f32(self.capacitor_left);
+        writer.write_f32(self.capacitor_right);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        for channel in self.channels.iter_mut() {
+            channel.load_state(reader)?;
+        }
+
+        self.frame_sequencer_step = reader.read_u8()?;
+        self.frame_sequencer_ticks = reader.read_u16()?;
+        self.sample_counter = reader.read_u32()?;
+        self.capacitor_left = reader.read_f32()?;
+        self.capacitor_right = reader.read_f32()?;
+
+        Ok(())
+    }
+}
+
+/// The length timer turns off the channel after a while, every channel has one
+pub(crate) struct LengthTimer {
+    counter: u16,
+
+    /// 64 for every channel except the wave one, which has 256
+    max: u16,
+}
+
+impl LengthTimer {
+    pub(crate) fn new(max: u16) -> Self {
+        Self { counter: 0, max }
+    }
+
+    /// The game writes the initial length, which counts up to the max
+    pub(crate) fn reload(&mut self, initial_length: u8) {
+        self.counter = self.max - initial_length as u16;
+    }
+
+    /// When triggering a channel, the length timer is set to the max if it has expired
+    pub(crate) fn trigger(&mut self) {
+        if self.counter == 0 {
+            self.counter = self.max;
+        }
+    }
+
+    /// Returns true if the channel has to be turned off. NRx4.6 tells us wheter or not
+    /// the timer is counting
+    pub(crate) fn clock(&mut self, nrx4: u8) -> bool {
+        if !nrx4.get_bit(6) || self.counter == 0 {
+            return false;
+        }
+
+        self.counter -= 1;
+        self.counter == 0
+    }
+}
+
+impl SaveState for LengthTimer {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.counter);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.counter = reader.read_u16()?;
+        Ok(())
+    }
+}
+
+/// The volume envelope is used by the square channels and the noise channel, NRx2 sets
+/// the initial volume and how it changes over time
+pub(crate) struct Envelope {
+    pub(crate) volume: u8,
+    is_increasing: bool,
+    period: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    pub(crate) fn new() -> Self {
+        Self {
+            volume: 0,
+            is_increasing: false,
+            period: 0,
+            timer: 0,
+        }
+    }
+
+    /// The envelope only reads NRx2 when the channel gets triggered
+    pub(crate) fn trigger(&mut self, nrx2: u8) {
+        self.volume = nrx2 >> 4;
+        self.is_increasing = nrx2.get_bit(3);
+        self.period = nrx2 & 0b111;
+        self.timer = self.period;
+    }
+
+    pub(crate) fn clock(&mut self) {
+        // A period of 0 means the envelope is disabled
+        if self.period == 0 {
+            return;
+        }
+
+        self.timer = self.timer.saturating_sub(1);
+
+        if self.timer != 0 {
+            return;
+        }
+
+        self.timer = self.period;
+
+        match self.is_increasing {
+            true if self.volume < 15 => self.volume += 1,
+            false if self.volume > 0 => self.volume -= 1,
+            _ => {}
+        }
+    }
+}
+
+impl SaveState for Envelope {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.volume);
+        writer.write_bool(self.is_increasing);
+        writer.write_u8(self.period);
+        writer.write_u8(self.timer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.volume = reader.read_u8()?;
+        self.is_increasing = reader.read_bool()?;
+        self.period = reader.read_u8()?;
+        self.timer = reader.read_u8()?;
+
+        Ok(())
+    }
+}
+
+/// The frequency of the square and wave channels is 11 bits, split in NRx3 and the lower
+/// 3 bits of NRx4
+pub(crate) fn get_frequency(nrx3: u8, nrx4: u8) -> u16 {
+    ((nrx4 as u16 & 0b111) << 8) | nrx3 as u16
+}
+
+/// Reads an APU register without the bus read masks, the APU needs the actual value
+pub(crate) fn get_register(bus: &Bus, address: u16) -> u8 {
+    bus.io[(address - IO_START as u16) as usize]
+}
+
+/// Like `get_register`, it writes to an APU register without triggering anything
+pub(crate) fn set_register(bus: &mut Bus, address: u16, value: u8) {
+    bus.io[(address - IO_START as u16) as usize] = value;
+}
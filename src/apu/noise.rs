@@ -0,0 +1,130 @@
+use crate::{
+    bus::Bus,
+    common::Bit,
+    consts::apu::{NR41, NR42, NR43, NR44},
+    save_state::{SaveState, SaveStateError, StateReader, StateWriter},
+};
+
+use super::{get_register, Channel, Envelope, LengthTimer};
+
+/// The base divisors selected by the lower 3 bits of NR43, these get shifted by the upper
+/// nibble of NR43 to get the actual period
+const DIVISORS: [u16; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+/// Channel 4 outputs pseudo-random noise, generated by a linear feedback shift register
+pub(crate) struct NoiseChannel {
+    is_enabled: bool,
+    length_timer: LengthTimer,
+    envelope: Envelope,
+
+    /// Counts down every dot, when it reaches 0 the LFSR gets shifted
+    frequency_timer: u32,
+
+    /// The linear feedback shift register, only the lower 15 bits are used
+    lfsr: u16,
+}
+
+impl NoiseChannel {
+    pub(crate) fn new() -> Self {
+        Self {
+            is_enabled: false,
+            length_timer: LengthTimer::new(64),
+            envelope: Envelope::new(),
+            frequency_timer: 0,
+            lfsr: 0,
+        }
+    }
+
+    fn period(&self, bus: &Bus) -> u32 {
+        let nr43 = get_register(bus, NR43);
+        let divisor = DIVISORS[(nr43 & 0b111) as usize] as u32;
+
+        divisor << (nr43 >> 4)
+    }
+}
+
+impl Channel for NoiseChannel {
+    fn trigger(&mut self, bus: &Bus) {
+        self.is_enabled = true;
+        self.length_timer.trigger();
+        self.envelope.trigger(get_register(bus, NR42));
+        self.frequency_timer = self.period(bus);
+        self.lfsr = 0x7FFF;
+    }
+
+    fn reload_length(&mut self, bus: &Bus) {
+        self.length_timer
+            .reload(get_register(bus, NR41) & 0b00111111);
+    }
+
+    fn tick(&mut self, bus: &Bus) {
+        self.frequency_timer = self.frequency_timer.saturating_sub(1);
+
+        if self.frequency_timer != 0 {
+            return;
+        }
+
+        self.frequency_timer = self.period(bus);
+
+        // With a clock shift of 14 or 15 the LFSR doesn't get any clocks
+        if get_register(bus, NR43) >> 4 >= 14 {
+            return;
+        }
+
+        // The new bit is the XOR of the two lowest bits, it's put in bit 14, and in bit 6
+        // as well when NR43.3 selects the short 7 bit mode
+        let new_bit = (self.lfsr & 0b1) ^ ((self.lfsr >> 1) & 0b1);
+        self.lfsr = (self.lfsr >> 1) | (new_bit << 14);
+
+        if get_register(bus, NR43).get_bit(3) {
+            self.lfsr = (self.lfsr & !(1 << 6)) | (new_bit << 6);
+        }
+    }
+
+    /// The output is the inverted lowest bit of the LFSR
+    fn output(&self, _bus: &Bus) -> u8 {
+        (!self.lfsr & 0b1) as u8 * self.envelope.volume
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.is_enabled
+    }
+
+    fn is_dac_enabled(&self, bus: &Bus) -> bool {
+        get_register(bus, NR42) & 0b11111000 != 0
+    }
+
+    fn disable(&mut self) {
+        self.is_enabled = false;
+    }
+
+    fn clock_length(&mut self, bus: &Bus) {
+        if self.length_timer.clock(get_register(bus, NR44)) {
+            self.is_enabled = false;
+        }
+    }
+
+    fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+}
+
+impl SaveState for NoiseChannel {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.is_enabled);
+        self.length_timer.save_state(writer);
+        self.envelope.save_state(writer);
+        writer.write_u32(self.frequency_timer);
+        writer.write_u16(self.lfsr);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.is_enabled = reader.read_bool()?;
+        self.length_timer.load_state(reader)?;
+        self.envelope.load_state(reader)?;
+        self.frequency_timer = reader.read_u32()?;
+        self.lfsr = reader.read_u16()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,230 @@
+use crate::{
+    bus::Bus,
+    common::Bit,
+    consts::apu::NR10,
+    save_state::{SaveState, SaveStateError, StateReader, StateWriter},
+};
+
+use super::{get_frequency, get_register, set_register, Channel, Envelope, LengthTimer};
+
+/// How the square wave looks for each of the 4 duty cycles, 12.5%, 25%, 50% and 75%
+const DUTY_PATTERNS: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 0],
+];
+
+/// Channel 1 and 2 are square waves, the only difference being that channel 1 also has a
+/// frequency sweep
+pub(crate) struct SquareChannel {
+    /// The address of NRx0, channel 2 does not have it but its registers are still laid
+    /// out as if it did
+    nrx0: u16,
+
+    has_sweep: bool,
+    is_enabled: bool,
+    length_timer: LengthTimer,
+    envelope: Envelope,
+
+    /// Counts down every dot, when it reaches 0 we go to the next step of the duty cycle
+    frequency_timer: u16,
+    duty_position: u8,
+
+    // The sweep changes the frequency periodically, `shadow_frequency` is a copy of the
+    // frequency that the sweep works on
+    is_sweep_enabled: bool,
+    sweep_timer: u8,
+    shadow_frequency: u16,
+}
+
+impl SquareChannel {
+    /// `channel` is 0 for channel 1 and 1 for channel 2
+    pub(crate) fn new(channel: u16, has_sweep: bool) -> Self {
+        Self {
+            nrx0: NR10 + channel * 5,
+            has_sweep,
+            is_enabled: false,
+            length_timer: LengthTimer::new(64),
+            envelope: Envelope::new(),
+            frequency_timer: 0,
+            duty_position: 0,
+            is_sweep_enabled: false,
+            sweep_timer: 0,
+            shadow_frequency: 0,
+        }
+    }
+
+    fn nrx0(&self, bus: &Bus) -> u8 {
+        get_register(bus, self.nrx0)
+    }
+
+    fn nrx1(&self, bus: &Bus) -> u8 {
+        get_register(bus, self.nrx0 + 1)
+    }
+
+    fn nrx2(&self, bus: &Bus) -> u8 {
+        get_register(bus, self.nrx0 + 2)
+    }
+
+    fn nrx4(&self, bus: &Bus) -> u8 {
+        get_register(bus, self.nrx0 + 4)
+    }
+
+    fn frequency(&self, bus: &Bus) -> u16 {
+        get_frequency(get_register(bus, self.nrx0 + 3), self.nrx4(bus))
+    }
+
+    /// Writes the frequency back in NRx3 and NRx4, this is done by the sweep
+    fn set_frequency(&self, bus: &mut Bus, frequency: u16) {
+        let nrx4 = (self.nrx4(bus) & 0b11111000) | (frequency >> 8) as u8;
+
+        set_register(bus, self.nrx0 + 3, frequency as u8);
+        set_register(bus, self.nrx0 + 4, nrx4);
+    }
+
+    /// Calculates the next frequency of the sweep, and turns off the channel if it
+    /// overflows
+    fn calculate_sweep_frequency(&mut self, bus: &Bus) -> u16 {
+        let nrx0 = self.nrx0(bus);
+        let shift = nrx0 & 0b111;
+        let difference = self.shadow_frequency >> shift;
+
+        let new_frequency = match nrx0.get_bit(3) {
+            false => self.shadow_frequency + difference,
+            true => self.shadow_frequency.wrapping_sub(difference),
+        };
+
+        if new_frequency > 2047 {
+            self.is_enabled = false;
+        }
+
+        new_frequency
+    }
+
+    /// A sweep period of 0 is treated as 8
+    fn sweep_period(&self, bus: &Bus) -> u8 {
+        match (self.nrx0(bus) >> 4) & 0b111 {
+            0 => 8,
+            period => period,
+        }
+    }
+}
+
+impl Channel for SquareChannel {
+    fn trigger(&mut self, bus: &Bus) {
+        self.is_enabled = true;
+        self.length_timer.trigger();
+        self.envelope.trigger(self.nrx2(bus));
+        self.frequency_timer = (2048 - self.frequency(bus)) * 4;
+
+        if self.has_sweep {
+            let nrx0 = self.nrx0(bus);
+
+            self.shadow_frequency = self.frequency(bus);
+            self.sweep_timer = self.sweep_period(bus);
+            self.is_sweep_enabled = nrx0 & 0b01110111 != 0;
+
+            // If there's a shift the overflow check is done immediately
+            if nrx0 & 0b111 != 0 {
+                self.calculate_sweep_frequency(bus);
+            }
+        }
+    }
+
+    fn reload_length(&mut self, bus: &Bus) {
+        self.length_timer.reload(self.nrx1(bus) & 0b00111111);
+    }
+
+    fn tick(&mut self, bus: &Bus) {
+        self.frequency_timer = self.frequency_timer.saturating_sub(1);
+
+        if self.frequency_timer == 0 {
+            self.frequency_timer = (2048 - self.frequency(bus)) * 4;
+            self.duty_position = (self.duty_position + 1) % 8;
+        }
+    }
+
+    fn output(&self, bus: &Bus) -> u8 {
+        let duty = (self.nrx1(bus) >> 6) as usize;
+        DUTY_PATTERNS[duty][self.duty_position as usize] * self.envelope.volume
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.is_enabled
+    }
+
+    /// The DAC is on if any of the upper 5 bits of NRx2 are set
+    fn is_dac_enabled(&self, bus: &Bus) -> bool {
+        self.nrx2(bus) & 0b11111000 != 0
+    }
+
+    fn disable(&mut self) {
+        self.is_enabled = false;
+    }
+
+    fn clock_length(&mut self, bus: &Bus) {
+        if self.length_timer.clock(self.nrx4(bus)) {
+            self.is_enabled = false;
+        }
+    }
+
+    fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    fn clock_sweep(&mut self, bus: &mut Bus) {
+        if !self.has_sweep {
+            return;
+        }
+
+        self.sweep_timer = self.sweep_timer.saturating_sub(1);
+
+        if self.sweep_timer != 0 {
+            return;
+        }
+
+        self.sweep_timer = self.sweep_period(bus);
+
+        // A period of 0 reloads the timer but doesn't actually sweep anything
+        if !self.is_sweep_enabled || (self.nrx0(bus) >> 4) & 0b111 == 0 {
+            return;
+        }
+
+        let new_frequency = self.calculate_sweep_frequency(bus);
+
+        if new_frequency <= 2047 && self.nrx0(bus) & 0b111 != 0 {
+            self.shadow_frequency = new_frequency;
+            self.set_frequency(bus, new_frequency);
+
+            // The overflow check is done again with the new frequency
+            self.calculate_sweep_frequency(bus);
+        }
+    }
+}
+
+impl SaveState for SquareChannel {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.is_enabled);
+        self.length_timer.save_state(writer);
+        self.envelope.save_state(writer);
+        writer.write_u16(self.frequency_timer);
+        writer.write_u8(self.duty_position);
+        writer.write_bool(self.is_sweep_enabled);
+        writer.write_u8(self.sweep_timer);
+        writer.write_u16(self.shadow_frequency);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.is_enabled = reader.read_bool()?;
+        self.length_timer.load_state(reader)?;
+        self.envelope.load_state(reader)?;
+        self.frequency_timer = reader.read_u16()?;
+        self.duty_position = reader.read_u8()?;
+        self.is_sweep_enabled = reader.read_bool()?;
+        self.sweep_timer = reader.read_u8()?;
+        self.shadow_frequency = reader.read_u16()?;
+
+        Ok(())
+    }
+}
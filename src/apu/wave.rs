@@ -0,0 +1,111 @@
+use crate::{
+    bus::Bus,
+    common::Bit,
+    consts::apu::{NR30, NR31, NR32, NR33, NR34, WAVE_RAM_START},
+    save_state::{SaveState, SaveStateError, StateReader, StateWriter},
+};
+
+use super::{get_frequency, get_register, Channel, LengthTimer};
+
+/// Channel 3 plays a custom wave stored in wave RAM, which is made of 32 4-bit samples
+pub(crate) struct WaveChannel {
+    is_enabled: bool,
+    length_timer: LengthTimer,
+
+    /// Counts down every dot, when it reaches 0 we go to the next sample
+    frequency_timer: u16,
+
+    /// Which of the 32 samples we are playing
+    position: u8,
+}
+
+impl WaveChannel {
+    pub(crate) fn new() -> Self {
+        Self {
+            is_enabled: false,
+            length_timer: LengthTimer::new(256),
+            frequency_timer: 0,
+            position: 0,
+        }
+    }
+
+    fn frequency(&self, bus: &Bus) -> u16 {
+        get_frequency(get_register(bus, NR33), get_register(bus, NR34))
+    }
+}
+
+impl Channel for WaveChannel {
+    fn trigger(&mut self, bus: &Bus) {
+        self.is_enabled = true;
+        self.length_timer.trigger();
+        self.frequency_timer = (2048 - self.frequency(bus)) * 2;
+        self.position = 0;
+    }
+
+    fn reload_length(&mut self, bus: &Bus) {
+        self.length_timer.reload(get_register(bus, NR31));
+    }
+
+    fn tick(&mut self, bus: &Bus) {
+        self.frequency_timer = self.frequency_timer.saturating_sub(1);
+
+        if self.frequency_timer == 0 {
+            self.frequency_timer = (2048 - self.frequency(bus)) * 2;
+            self.position = (self.position + 1) % 32;
+        }
+    }
+
+    fn output(&self, bus: &Bus) -> u8 {
+        // Every byte holds two samples, the upper nibble is played first
+        let byte = get_register(bus, WAVE_RAM_START + self.position as u16 / 2);
+        let sample = match self.position % 2 {
+            0 => byte >> 4,
+            _ => byte & 0x0F,
+        };
+
+        // Bits 5-6 of NR32 select the volume, which is done by shifting the sample
+        match (get_register(bus, NR32) >> 5) & 0b11 {
+            0 => 0,
+            1 => sample,
+            2 => sample >> 1,
+            _ => sample >> 2,
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.is_enabled
+    }
+
+    /// The wave channel's DAC is controlled directly by NR30.7
+    fn is_dac_enabled(&self, bus: &Bus) -> bool {
+        get_register(bus, NR30).get_bit(7)
+    }
+
+    fn disable(&mut self) {
+        self.is_enabled = false;
+    }
+
+    fn clock_length(&mut self, bus: &Bus) {
+        if self.length_timer.clock(get_register(bus, NR34)) {
+            self.is_enabled = false;
+        }
+    }
+}
+
+impl SaveState for WaveChannel {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.is_enabled);
+        self.length_timer.save_state(writer);
+        writer.write_u16(self.frequency_timer);
+        writer.write_u8(self.position);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.is_enabled = reader.read_bool()?;
+        self.length_timer.load_state(reader)?;
+        self.frequency_timer = reader.read_u16()?;
+        self.position = reader.read_u8()?;
+
+        Ok(())
+    }
+}
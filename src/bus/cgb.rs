@@ -0,0 +1,162 @@
+//! The GameBoy Color has twice the VRAM and four times the work ram of the original, both
+//! split in banks the game can switch between. It also has its own palettes, which are
+//! stored in a separate memory only accessible through BCPD and OCPD, and it can run the
+//! CPU twice as fast.
+//!
+//! This is a really good resource: https://gbdev.io/pandocs/CGB_Registers.html
+
+use crate::{
+    common::Bit,
+    consts::{
+        bus::{VIDEO_RAM_SIZE, WORK_RAM_BANK_SIZE},
+        cgb::*,
+    },
+    gpu::GpuState,
+};
+
+use super::Bus;
+
+impl Bus {
+    /// The VRAM bank the CPU sees at 0x8000-0x9FFF, selected by VBK
+    fn get_video_ram_bank(&self) -> usize {
+        match self.is_cgb {
+            true => (self.io[0x4F] & 0b1) as usize,
+            false => 0,
+        }
+    }
+
+    /// The work ram bank the CPU sees at 0xD000-0xDFFF, selected by SVBK. Bank 0 is always
+    /// at 0xC000-0xCFFF, so selecting it gives bank 1 instead
+    fn get_work_ram_bank(&self) -> usize {
+        match self.is_cgb {
+            true => (self.io[0x70] & 0b111).max(1) as usize,
+            false => 1,
+        }
+    }
+
+    /// Where `address` (from 0x8000 to 0x9FFF) is in `video_ram` for the selected bank
+    pub(super) fn get_video_ram_index(&self, address: u16) -> usize {
+        self.get_video_ram_bank() * VIDEO_RAM_SIZE + (address - 0x8000) as usize
+    }
+
+    /// Where `address` (from 0xC000 to 0xDFFF) is in `work_ram` for the selected bank
+    pub(super) fn get_work_ram_index(&self, address: u16) -> usize {
+        match address {
+            0xC000..=0xCFFF => (address - 0xC000) as usize,
+            _ => self.get_work_ram_bank() * WORK_RAM_BANK_SIZE + (address - 0xD000) as usize,
+        }
+    }
+
+    /// The GPU doesn't care about the bank selected by the CPU, it reads the tile maps
+    /// from bank 0 and their attributes from bank 1
+    pub(crate) fn read_video_ram(&self, bank: u8, address: u16) -> u8 {
+        self.video_ram[bank as usize * VIDEO_RAM_SIZE + (address - 0x8000) as usize]
+    }
+
+    /// Whether or not the CPU runs at double speed, the GPU and the APU don't change
+    /// speed, so they get half of the dots every cycle
+    pub(crate) fn is_double_speed(&self) -> bool {
+        self.is_cgb && self.io[0x4D].get_bit(7)
+    }
+
+    /// `STOP` switches the speed instead of stopping when the game asked for it in KEY1,
+    /// returns whether or not that happened
+    pub(crate) fn switch_speed(&mut self) -> bool {
+        if !self.is_cgb || !self.io[0x4D].get_bit(0) {
+            return false;
+        }
+
+        self.io[0x4D] = 0b01111110 | (!self.is_double_speed() as u8) << 7;
+        true
+    }
+
+    /// The color of a pixel in the RGB555 format, `color` is the index in the palette
+    pub(crate) fn get_cgb_color(&self, is_sprite: bool, palette: u8, color: u8) -> u16 {
+        let palette_ram = match is_sprite {
+            false => &self.background_palettes,
+            true => &self.object_palettes,
+        };
+
+        let index = palette as usize * 8 + color as usize * 2;
+        u16::from_le_bytes([palette_ram[index], palette_ram[index + 1]]) & 0x7FFF
+    }
+
+    pub(super) fn read_cgb_register(&self, address: u16) -> u8 {
+        match address {
+            KEY1 => self.io[0x4D],
+            VBK => 0b11111110 | self.io[0x4F],
+            HDMA1..=HDMA4 => 0xFF,
+            HDMA5 => self.read_hdma5(),
+            BCPS => 0b01000000 | self.io[0x68],
+            OCPS => 0b01000000 | self.io[0x6A],
+            BCPD => self.read_palette_data(false),
+            OCPD => self.read_palette_data(true),
+            SVBK => 0b11111000 | self.io[0x70],
+            _ => unreachable!(),
+        }
+    }
+
+    pub(super) fn write_cgb_register(&mut self, address: u16, value: u8) {
+        match address {
+            // Only the bit that prepares the speed switch can be written
+            KEY1 => self.io[0x4D] = (self.io[0x4D] & 0b10000000) | 0b01111110 | (value & 0b1),
+            VBK => self.io[0x4F] = value & 0b1,
+            HDMA1..=HDMA4 => self.io[(address - 0xFF00) as usize] = value,
+            HDMA5 => self.start_vram_dma(value),
+            BCPS => self.io[0x68] = value & 0b10111111,
+            OCPS => self.io[0x6A] = value & 0b10111111,
+            BCPD => self.write_palette_data(false, value),
+            OCPD => self.write_palette_data(true, value),
+            SVBK => self.io[0x70] = value & 0b111,
+            _ => unreachable!(),
+        }
+    }
+
+    /// The palettes can't be accessed while the GPU is drawing
+    fn read_palette_data(&self, is_sprite: bool) -> u8 {
+        if self.gpu_state == GpuState::PixelTransfer {
+            return 0xFF;
+        }
+
+        let (palette_ram, specification) = match is_sprite {
+            false => (&self.background_palettes, self.io[0x68]),
+            true => (&self.object_palettes, self.io[0x6A]),
+        };
+
+        palette_ram[(specification & 0b00111111) as usize]
+    }
+
+    /// BCPS and OCPS select the byte that's written, when their bit 7 is set the index is
+    /// incremented after every write, even if the write was blocked
+    fn write_palette_data(&mut self, is_sprite: bool, value: u8) {
+        let is_blocked = self.gpu_state == GpuState::PixelTransfer;
+
+        let (palette_ram, specification) = match is_sprite {
+            false => (&mut self.background_palettes, &mut self.io[0x68]),
+            true => (&mut self.object_palettes, &mut self.io[0x6A]),
+        };
+
+        let index = *specification & 0b00111111;
+        if !is_blocked {
+            palette_ram[index as usize] = value;
+        }
+
+        if specification.get_bit(7) {
+            *specification = 0b10000000 | (index.wrapping_add(1) & 0b00111111);
+        }
+    }
+}
+
+/// The registers that only exist on the GameBoy Color, on the original GameBoy these are
+/// just unused io
+pub(super) fn is_cgb_register(address: u16) -> bool {
+    matches!(
+        address,
+        KEY1 | VBK | HDMA1..=HDMA5 | BCPS | BCPD | OCPS | OCPD | SVBK
+    )
+}
+
+/// The boot rom fills the palettes with white
+pub(super) fn new_palette_ram() -> [u8; PALETTE_RAM_SIZE] {
+    [0xFF; PALETTE_RAM_SIZE]
+}
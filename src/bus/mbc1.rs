@@ -1,7 +1,12 @@
-use crate::common::Bit;
+use crate::{
+    common::Bit,
+    header::CartridgeHeader,
+    save_state::{SaveState, SaveStateError, StateReader, StateWriter},
+};
 
 use super::{
-    calculate_ram_address, calculate_rom_address, get_ram_size, get_rom_size, Mbc, ROM_BANK_SIZE,
+    calculate_ram_address, calculate_rom_address, get_save_data, load_save_data, BusError, Mbc,
+    ROM_BANK_SIZE,
 };
 
 enum BankingMode {
@@ -18,12 +23,14 @@ pub(crate) struct Mbc1 {
     banking_mode: BankingMode,
     rom_size: usize,
     ram_size: usize,
+    has_battery: bool,
 }
 
 impl Mbc for Mbc1 {
-    fn new(mut rom: Vec<u8>) -> Self {
-        let rom_size = get_rom_size(&rom);
-        let ram_size = get_ram_size(&rom);
+    fn new(mut rom: Vec<u8>, header: &CartridgeHeader) -> Self {
+        let rom_size = header.rom_size();
+        let ram_size = header.ram_size();
+        let has_battery = header.has_battery();
 
         // Resizing given rom
         rom.resize(rom_size, 0);
@@ -40,6 +47,7 @@ impl Mbc for Mbc1 {
             banking_mode: BankingMode::Simple,
             rom_size,
             ram_size,
+            has_battery,
         }
     }
 
@@ -128,6 +136,14 @@ impl Mbc for Mbc1 {
             };
         }
     }
+
+    fn get_save_data(&self) -> Option<Vec<u8>> {
+        get_save_data(self.has_battery, &self.external_ram)
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) -> Result<(), BusError> {
+        load_save_data(self.has_battery, &mut self.external_ram, data)
+    }
 }
 
 impl Mbc1 {
@@ -145,3 +161,26 @@ impl Mbc1 {
         }
     }
 }
+
+impl SaveState for Mbc1 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_vec(&self.external_ram);
+        writer.write_bool(self.is_ram_enabled);
+        writer.write_u8(self.rom_bank_number as u8);
+        writer.write_u8(self.ram_bank_number as u8);
+        writer.write_bool(matches!(self.banking_mode, BankingMode::Advanced));
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        reader.read_vec(&mut self.external_ram)?;
+        self.is_ram_enabled = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u8()? as usize;
+        self.ram_bank_number = reader.read_u8()? as usize;
+        self.banking_mode = match reader.read_bool()? {
+            false => BankingMode::Simple,
+            true => BankingMode::Advanced,
+        };
+
+        Ok(())
+    }
+}
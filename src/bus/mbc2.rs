@@ -0,0 +1,126 @@
+//! MBC2 has no external ram, instead it has 512 half bytes of ram built in the chip
+
+use crate::{
+    common::Bit,
+    header::CartridgeHeader,
+    save_state::{SaveState, SaveStateError, StateReader, StateWriter},
+};
+
+use super::{calculate_rom_address, get_save_data, load_save_data, BusError, Mbc, ROM_BANK_SIZE};
+
+const BUILT_IN_RAM_SIZE: usize = 512;
+
+pub(crate) struct Mbc2 {
+    rom: Vec<u8>,
+
+    /// Only the lower nibble of every byte is used
+    built_in_ram: [u8; BUILT_IN_RAM_SIZE],
+    is_ram_enabled: bool,
+    rom_bank_number: usize,
+    rom_size: usize,
+    has_battery: bool,
+}
+
+impl Mbc for Mbc2 {
+    fn new(mut rom: Vec<u8>, header: &CartridgeHeader) -> Self {
+        let rom_size = header.rom_size();
+        let has_battery = header.has_battery();
+
+        // Resizing given rom
+        rom.resize(rom_size, 0);
+
+        Self {
+            rom,
+            built_in_ram: [0; BUILT_IN_RAM_SIZE],
+            is_ram_enabled: false,
+            rom_bank_number: 1,
+            rom_size,
+            has_battery,
+        }
+    }
+
+    /// Section 0 is just the first bank
+    fn get_rom_section_0(&self, address: u16) -> u8 {
+        self.rom[address as usize]
+    }
+
+    fn get_rom_section_1(&self, address: u16) -> u8 {
+        let new_address = calculate_rom_address(
+            self.rom_size,
+            address - ROM_BANK_SIZE as u16,
+            self.rom_bank_number,
+        );
+
+        self.rom[new_address]
+    }
+
+    /// The 512 half bytes are repeated all over A000-BFFF, and since the upper nibble does
+    /// not exist it's always read as 1s
+    fn get_external_ram(&self, address: u16) -> u8 {
+        if !self.is_ram_enabled {
+            return 0xFF;
+        }
+
+        self.built_in_ram[address as usize % BUILT_IN_RAM_SIZE] | 0xF0
+    }
+
+    fn set_external_ram(&mut self, address: u16, value: u8) {
+        if !self.is_ram_enabled {
+            return;
+        }
+
+        self.built_in_ram[address as usize % BUILT_IN_RAM_SIZE] = value & 0x0F;
+    }
+
+    fn direct_rom_write(&mut self, address: u16, value: u8) {
+        self.rom[address as usize] = value;
+    }
+
+    fn signal_rom_write(&mut self, address: u16, value: u8) {
+        // There's only one register at 0000-3FFF, bit 8 of the address tells us wheter
+        // the game is enabling ram or selecting a rom bank
+        if address > 0x3FFF {
+            return;
+        }
+
+        match address.get_bit(8) {
+            false => self.is_ram_enabled = value & 0x0F == 0xA,
+            true => {
+                self.rom_bank_number = value as usize & 0b00001111;
+
+                // The rom bank cannot be 0
+                if self.rom_bank_number == 0 {
+                    self.rom_bank_number = 1;
+                }
+            }
+        }
+    }
+
+    fn get_save_data(&self) -> Option<Vec<u8>> {
+        get_save_data(self.has_battery, &self.built_in_ram)
+    }
+
+    /// Other emulators store the upper nibble in different ways, so we throw it away
+    fn load_save_data(&mut self, data: &[u8]) -> Result<(), BusError> {
+        load_save_data(self.has_battery, &mut self.built_in_ram, data)?;
+        self.built_in_ram.iter_mut().for_each(|byte| *byte &= 0x0F);
+
+        Ok(())
+    }
+}
+
+impl SaveState for Mbc2 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.built_in_ram);
+        writer.write_bool(self.is_ram_enabled);
+        writer.write_u8(self.rom_bank_number as u8);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        reader.read_bytes(&mut self.built_in_ram)?;
+        self.is_ram_enabled = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u8()? as usize;
+
+        Ok(())
+    }
+}
@@ -1,7 +1,12 @@
-// TODO: Implement timer and day counter
+use crate::{
+    header::CartridgeHeader,
+    rtc::{Rtc, TimeSource},
+    save_state::{SaveState, SaveStateError, StateReader, StateWriter},
+};
 
 use super::{
-    calculate_ram_address, calculate_rom_address, get_ram_size, get_rom_size, Mbc, ROM_BANK_SIZE,
+    calculate_ram_address, calculate_rom_address, get_save_data, load_save_data, BusError, Mbc,
+    ROM_BANK_SIZE,
 };
 
 pub(crate) struct Mbc3 {
@@ -10,14 +15,25 @@ pub(crate) struct Mbc3 {
     are_ram_and_timer_enabled: bool,
     rom_size: usize,
     ram_size: usize,
+    has_battery: bool,
+
+    /// 0x00-0x03 select a ram bank, while 0x08-0x0C map a clock register in its place
     ram_bank_number: usize,
     rom_bank_number: usize,
+
+    /// Only cartridges of type 0x0F and 0x10 have a clock
+    rtc: Option<Rtc>,
 }
 
 impl Mbc for Mbc3 {
-    fn new(mut rom: Vec<u8>) -> Self {
-        let rom_size = get_rom_size(&rom);
-        let ram_size = get_ram_size(&rom);
+    fn new(mut rom: Vec<u8>, header: &CartridgeHeader) -> Self {
+        let rom_size = header.rom_size();
+        let ram_size = header.ram_size();
+        let has_battery = header.has_battery();
+        let rtc = match header.cartridge_type {
+            0x0F | 0x10 => Some(Rtc::new()),
+            _ => None,
+        };
 
         // Resizing given rom
         rom.resize(rom_size, 0);
@@ -33,6 +49,8 @@ impl Mbc for Mbc3 {
             ram_bank_number: 0,
             rom_size,
             ram_size,
+            has_battery,
+            rtc,
         }
     }
 
@@ -53,13 +71,39 @@ impl Mbc for Mbc3 {
     }
 
     fn get_external_ram(&self, address: u16) -> u8 {
-        let new_address = calculate_ram_address(self.ram_size, address, self.ram_bank_number);
-        self.external_ram[new_address]
+        if !self.are_ram_and_timer_enabled {
+            return 0xFF;
+        }
+
+        match (self.ram_bank_number, &self.rtc) {
+            (0x08..=0x0C, Some(rtc)) => rtc.read(self.ram_bank_number as u8),
+            (0x00..=0x03, _) if self.ram_size != 0 => {
+                let new_address =
+                    calculate_ram_address(self.ram_size, address, self.ram_bank_number);
+
+                self.external_ram[new_address]
+            }
+
+            _ => 0xFF,
+        }
     }
 
     fn set_external_ram(&mut self, address: u16, value: u8) {
-        let new_address = calculate_ram_address(self.ram_size, address, self.ram_bank_number);
-        self.external_ram[new_address] = value;
+        if !self.are_ram_and_timer_enabled {
+            return;
+        }
+
+        match (self.ram_bank_number, &mut self.rtc) {
+            (0x08..=0x0C, Some(rtc)) => rtc.write(self.ram_bank_number as u8, value),
+            (0x00..=0x03, _) if self.ram_size != 0 => {
+                let new_address =
+                    calculate_ram_address(self.ram_size, address, self.ram_bank_number);
+
+                self.external_ram[new_address] = value;
+            }
+
+            _ => {}
+        }
     }
 
     fn direct_rom_write(&mut self, address: u16, value: u8) {
@@ -80,9 +124,68 @@ impl Mbc for Mbc3 {
             return;
         }
 
-        // Address 4000-5FFF is the two bit ram bank number register
+        // Address 4000-5FFF selects either a ram bank or a clock register
         if (0x4000..=0x5FFF).contains(&address) {
-            self.ram_bank_number = value as usize & 0b00000011;
+            self.ram_bank_number = value as usize & 0b00001111;
+            return;
+        }
+
+        // Address 6000-7FFF latches the clock registers, so the game can read them
+        if let Some(rtc) = &mut self.rtc {
+            rtc.signal_latch_write(value);
+        }
+    }
+
+    /// The clock data goes after the ram, which is what other emulators do as well
+    fn get_save_data(&self) -> Option<Vec<u8>> {
+        let mut save_data = get_save_data(self.has_battery, &self.external_ram)?;
+
+        if let Some(rtc) = &self.rtc {
+            save_data.append(&mut rtc.get_save_data());
+        }
+
+        Some(save_data)
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) -> Result<(), BusError> {
+        load_save_data(self.has_battery, &mut self.external_ram, data)?;
+
+        if let Some(rtc) = &mut self.rtc {
+            rtc.load_save_data(&data[self.external_ram.len()..]);
+        }
+
+        Ok(())
+    }
+
+    fn set_time_source(&mut self, time_source: Box<dyn TimeSource>) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.set_time_source(time_source);
         }
     }
 }
+
+impl SaveState for Mbc3 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_vec(&self.external_ram);
+        writer.write_bool(self.are_ram_and_timer_enabled);
+        writer.write_u8(self.rom_bank_number as u8);
+        writer.write_u8(self.ram_bank_number as u8);
+
+        if let Some(rtc) = &self.rtc {
+            rtc.save_state(writer);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        reader.read_vec(&mut self.external_ram)?;
+        self.are_ram_and_timer_enabled = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u8()? as usize;
+        self.ram_bank_number = reader.read_u8()? as usize;
+
+        if let Some(rtc) = &mut self.rtc {
+            rtc.load_state(reader)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,156 @@
+use crate::{
+    header::CartridgeHeader,
+    save_state::{SaveState, SaveStateError, StateReader, StateWriter},
+};
+
+use super::{
+    calculate_ram_address, calculate_rom_address, get_save_data, load_save_data, BusError, Mbc,
+    ROM_BANK_SIZE,
+};
+
+pub(crate) struct Mbc5 {
+    rom: Vec<u8>,
+    external_ram: Vec<u8>,
+    is_ram_enabled: bool,
+    rom_bank_number: usize,
+    ram_bank_number: usize,
+    rom_size: usize,
+    ram_size: usize,
+    has_battery: bool,
+
+    /// Cartridges of type 0x1C-0x1E have a motor, which is controlled by bit 3 of the ram
+    /// bank number register instead of selecting a bank
+    has_rumble: bool,
+    is_rumbling: bool,
+}
+
+impl Mbc for Mbc5 {
+    fn new(mut rom: Vec<u8>, header: &CartridgeHeader) -> Self {
+        let rom_size = header.rom_size();
+        let ram_size = header.ram_size();
+        let has_battery = header.has_battery();
+        let has_rumble = (0x1C..=0x1E).contains(&header.cartridge_type);
+
+        // Resizing given rom
+        rom.resize(rom_size, 0);
+
+        // Creating the new ram
+        let ram: Vec<u8> = vec![0; ram_size];
+
+        Self {
+            rom,
+            external_ram: ram,
+            is_ram_enabled: false,
+            rom_bank_number: 1,
+            ram_bank_number: 0,
+            rom_size,
+            ram_size,
+            has_battery,
+            has_rumble,
+            is_rumbling: false,
+        }
+    }
+
+    /// Section 0 is just the first bank
+    fn get_rom_section_0(&self, address: u16) -> u8 {
+        self.rom[address as usize]
+    }
+
+    /// Section 1 can be any of the 512 banks, bank 0 included
+    fn get_rom_section_1(&self, address: u16) -> u8 {
+        let new_address = calculate_rom_address(
+            self.rom_size,
+            address - ROM_BANK_SIZE as u16,
+            self.rom_bank_number,
+        );
+
+        self.rom[new_address]
+    }
+
+    fn get_external_ram(&self, address: u16) -> u8 {
+        if !self.is_ram_enabled || self.ram_size == 0 {
+            return 0xFF;
+        }
+
+        let new_address = calculate_ram_address(self.ram_size, address, self.ram_bank_number);
+        self.external_ram[new_address]
+    }
+
+    fn set_external_ram(&mut self, address: u16, value: u8) {
+        if !self.is_ram_enabled || self.ram_size == 0 {
+            return;
+        }
+
+        let new_address = calculate_ram_address(self.ram_size, address, self.ram_bank_number);
+        self.external_ram[new_address] = value;
+    }
+
+    fn direct_rom_write(&mut self, address: u16, value: u8) {
+        self.rom[address as usize] = value;
+    }
+
+    fn signal_rom_write(&mut self, address: u16, value: u8) {
+        // You enable or disable ram by writing to 0000-1FFF, contrary to MBC1 only "A"
+        // enables it, the upper nibble matters too
+        if address <= 0x1FFF {
+            self.is_ram_enabled = value == 0x0A;
+            return;
+        }
+
+        // Address 2000-2FFF is the lower 8 bits of the rom bank number
+        if (0x2000..=0x2FFF).contains(&address) {
+            self.rom_bank_number = (self.rom_bank_number & 0x100) | value as usize;
+            return;
+        }
+
+        // Address 3000-3FFF is the 9th bit of the rom bank number
+        if (0x3000..=0x3FFF).contains(&address) {
+            self.rom_bank_number = (self.rom_bank_number & 0xFF) | (value as usize & 0b1) << 8;
+            return;
+        }
+
+        // Address 4000-5FFF is the four bit ram bank number register, on rumble
+        // cartridges bit 3 turns on the motor instead
+        if (0x4000..=0x5FFF).contains(&address) {
+            match self.has_rumble {
+                false => self.ram_bank_number = value as usize & 0b00001111,
+                true => {
+                    self.ram_bank_number = value as usize & 0b00000111;
+                    self.is_rumbling = value & 0b00001000 != 0;
+                }
+            }
+        }
+    }
+
+    fn get_save_data(&self) -> Option<Vec<u8>> {
+        get_save_data(self.has_battery, &self.external_ram)
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) -> Result<(), BusError> {
+        load_save_data(self.has_battery, &mut self.external_ram, data)
+    }
+
+    fn is_rumbling(&self) -> bool {
+        self.is_rumbling
+    }
+}
+
+impl SaveState for Mbc5 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_vec(&self.external_ram);
+        writer.write_bool(self.is_ram_enabled);
+        writer.write_u16(self.rom_bank_number as u16);
+        writer.write_u8(self.ram_bank_number as u8);
+        writer.write_bool(self.is_rumbling);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        reader.read_vec(&mut self.external_ram)?;
+        self.is_ram_enabled = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u16()? as usize;
+        self.ram_bank_number = reader.read_u8()? as usize;
+        self.is_rumbling = reader.read_bool()?;
+
+        Ok(())
+    }
+}
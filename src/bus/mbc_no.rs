@@ -1,6 +1,11 @@
 //! The most simple MBC, it just has rom and external ram, no banks or anything
 
-use super::{vector_to_array, Mbc};
+use crate::{
+    header::CartridgeHeader,
+    save_state::{SaveState, SaveStateError, StateReader, StateWriter},
+};
+
+use super::{get_save_data, load_save_data, vector_to_array, BusError, Mbc};
 
 const ROM_SIZE: usize = 0x8000;
 const EXTERNAL_RAM_SIZE: usize = 0x2000;
@@ -8,11 +13,18 @@ const EXTERNAL_RAM_SIZE: usize = 0x2000;
 pub(crate) struct NoMbc {
     rom: [u8; ROM_SIZE],
     external_ram: [u8; EXTERNAL_RAM_SIZE],
+
+    /// The ram that's actually on the cartridge according to the header, which is the only
+    /// part that gets saved
+    ram_size: usize,
+    has_battery: bool,
 }
 
 impl Mbc for NoMbc {
-    fn new(rom: Vec<u8>) -> Self {
+    fn new(rom: Vec<u8>, header: &CartridgeHeader) -> Self {
         Self {
+            ram_size: header.ram_size().min(EXTERNAL_RAM_SIZE),
+            has_battery: header.has_battery(),
             rom: vector_to_array::<ROM_SIZE>(rom),
             external_ram: [0; EXTERNAL_RAM_SIZE],
         }
@@ -40,4 +52,26 @@ impl Mbc for NoMbc {
 
     // We do nothing when writing to ROM
     fn signal_rom_write(&mut self, _address: u16, _value: u8) {}
+
+    fn get_save_data(&self) -> Option<Vec<u8>> {
+        get_save_data(self.has_battery, &self.external_ram[..self.ram_size])
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) -> Result<(), BusError> {
+        load_save_data(
+            self.has_battery,
+            &mut self.external_ram[..self.ram_size],
+            data,
+        )
+    }
+}
+
+impl SaveState for NoMbc {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.external_ram);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        reader.read_bytes(&mut self.external_ram)
+    }
 }
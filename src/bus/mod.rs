@@ -1,21 +1,44 @@
 use std::{error::Error, fmt::Display, fs::File, io::Read};
 
+use cgb::{is_cgb_register, new_palette_ram};
 use mbc1::Mbc1;
+use mbc2::Mbc2;
 use mbc3::Mbc3;
+use mbc5::Mbc5;
 use mbc_no::NoMbc;
+use oam_dma::OamDma;
+use vram_dma::VramDma;
 
 use crate::{
-    common::merge_two_u8s_into_u16,
-    consts::{bus::*, cpu::DIV},
-    registers::Registers,
+    common::Bit,
+    consts::{
+        apu::*,
+        bus::*,
+        cgb::PALETTE_RAM_SIZE,
+        cpu::{DIV, IF, TIMA},
+        gpu::STAT,
+        joypad::JOYP,
+        serial::SC,
+    },
+    gpu::GpuState,
+    header::{CartridgeHeader, HEADER_END},
+    rtc::TimeSource,
+    save_state::{SaveState, SaveStateError, StateReader, StateWriter},
+    sgb::Sgb,
 };
 
+mod cgb;
 mod mbc1;
+mod mbc2;
 mod mbc3;
+mod mbc5;
 mod mbc_no;
+mod oam_dma;
+mod vram_dma;
 
-pub trait Mbc: Send {
-    fn new(rom: Vec<u8>) -> Self
+pub trait Mbc: Send + SaveState {
+    /// `header` is the one parsed from `rom`, so it doesn't have to be parsed again
+    fn new(rom: Vec<u8>, header: &CartridgeHeader) -> Self
     where
         Self: Sized;
 
@@ -38,68 +61,177 @@ pub trait Mbc: Send {
     /// Writes to rom without signaling anything, this is the only way to write to rom and
     /// it's used in examples
     fn direct_rom_write(&mut self, address: u16, value: u8);
+
+    /// Returns the content of the external ram so it can be saved to disk, this is `None`
+    /// when the cartridge has no battery, because the game would not expect its ram to
+    /// survive being turned off
+    fn get_save_data(&self) -> Option<Vec<u8>>;
+
+    /// Loads the external ram from the data given by `get_save_data`, this is the same
+    /// format as the `.sav` files of other emulators
+    fn load_save_data(&mut self, data: &[u8]) -> Result<(), BusError>;
+
+    /// Some cartridges have a vibration motor, frontends can use this to make the
+    /// controller vibrate
+    fn is_rumbling(&self) -> bool {
+        false
+    }
+
+    /// Only used by cartridges with a real time clock
+    #[allow(unused_variables)]
+    fn set_time_source(&mut self, time_source: Box<dyn TimeSource>) {}
 }
 
 pub struct Bus {
     pub mbc: Box<dyn Mbc>,
-    pub video_ram: [u8; VIDEO_RAM_SIZE],
-    pub work_ram: [u8; WORK_RAM_SIZE],
+    pub video_ram: [u8; VIDEO_RAM_SIZE * VIDEO_RAM_BANKS],
+    pub work_ram: [u8; WORK_RAM_BANK_SIZE * WORK_RAM_BANKS],
     pub eom: [u8; EOM_SIZE],
     pub unusable_ram: [u8; UNUSABLE_RAM_SIZE], // TODO: This should not be usuable
     pub io: [u8; IO_SIZE],
     pub high_ram: [u8; HIGH_RAM_SIZE],
     pub ie: u8,
 
-    /// Gets true when the user writes to OAM DMA register
-    pub needs_to_dispatch_oam_dma: bool,
+    /// The boot rom is mapped over the first 256 bytes of the cartridge until the game
+    /// writes to BOOT, this is `None` when it's not mapped or it was never loaded
+    pub(crate) boot_rom: Option<[u8; BOOT_ROM_SIZE]>,
+
+    /// The buttons that are held down, as given by `Joypad::pressed_buttons`
+    pub(crate) pressed_buttons: u8,
+
+    /// What the GPU is doing, the CPU can't access VRAM and OAM while the GPU uses them
+    pub(crate) gpu_state: GpuState,
+
+    /// The OAM DMA transfer started by writing to DMA
+    pub(crate) oam_dma: OamDma,
+
+    /// Whether or not the game runs in GameBoy Color mode, this enables the VRAM and work
+    /// ram banks, the color palettes and the other CGB registers
+    pub(crate) is_cgb: bool,
 
-    /// Gets true when the emulator writes to DIV, this means that we must reset the div
-    /// register internal cycle counter
+    /// The CGB color palettes, accessed through BCPS/BCPD and OCPS/OCPD
+    pub(crate) background_palettes: [u8; PALETTE_RAM_SIZE],
+    pub(crate) object_palettes: [u8; PALETTE_RAM_SIZE],
+
+    /// The VRAM DMA transfer started by writing to HDMA5
+    pub(crate) vram_dma: VramDma,
+
+    /// The Super GameBoy, only present for games that support it. It listens to the
+    /// packets the game sends through JOYP
+    pub(crate) sgb: Option<Box<Sgb>>,
+
+    /// Gets true when the emulator writes to DIV, this means that we must reset the
+    /// timer's internal counter
     pub(crate) needs_to_reset_div_register: bool,
-}
 
-impl Bus {
-    pub(crate) fn new(rom_path: &str) -> Result<Self, BusError> {
-        // ROM loading
-        let mut rom: Vec<u8> = Vec::new();
+    /// Gets true when the game writes to TIMA, which cancels a reload after an overflow
+    pub(crate) was_tima_written: bool,
 
-        let mut rom_file = match File::open(rom_path) {
-            Ok(rom_file) => rom_file,
-            Err(_) => return Err(BusError::CouldNotFindRom),
-        };
+    /// Gets true when the game writes to SC, which restarts the serial transfer
+    pub(crate) needs_to_start_serial_transfer: bool,
 
-        match rom_file.read_to_end(&mut rom) {
-            Ok(_) => {}
-            Err(_) => return Err(BusError::CouldNotReadRom),
-        }
+    /// Gets true when the game writes to NRx4 with bit 7 set, which restarts the channel
+    pub(crate) needs_to_trigger_channel: [bool; 4],
 
-        // Actual returning
-        Ok(Self {
-            mbc: new_mbc(rom),
-            video_ram: [0u8; VIDEO_RAM_SIZE],
-            work_ram: [0u8; WORK_RAM_SIZE],
-            eom: [0u8; EOM_SIZE],
-            high_ram: [0u8; HIGH_RAM_SIZE],
-            ie: 0u8,
-            io: new_io(),
-            unusable_ram: [0u8; UNUSABLE_RAM_SIZE],
-            needs_to_dispatch_oam_dma: false,
-            needs_to_reset_div_register: false,
-        })
-    }
+    /// Gets true when the game writes to NRx1, which reloads the channel's length timer
+    pub(crate) needs_to_reload_length: [bool; 4],
+}
 
-    pub(crate) fn new_from_rom_array(rom: Vec<u8>) -> Self {
+impl Bus {
+    pub(crate) fn new(mbc: Box<dyn Mbc>, is_cgb: bool, is_sgb: bool) -> Self {
         Self {
-            mbc: new_mbc(rom),
-            video_ram: [0u8; VIDEO_RAM_SIZE],
-            work_ram: [0u8; WORK_RAM_SIZE],
+            mbc,
+            video_ram: [0u8; VIDEO_RAM_SIZE * VIDEO_RAM_BANKS],
+            work_ram: [0u8; WORK_RAM_BANK_SIZE * WORK_RAM_BANKS],
             eom: [0u8; EOM_SIZE],
             high_ram: [0u8; HIGH_RAM_SIZE],
             ie: 0u8,
-            io: new_io(),
+            io: match is_cgb {
+                true => new_cgb_io(),
+                false => new_io(),
+            },
             unusable_ram: [0u8; UNUSABLE_RAM_SIZE],
-            needs_to_dispatch_oam_dma: false,
+            boot_rom: None,
+            pressed_buttons: 0,
+            gpu_state: GpuState::OamSearch,
+            oam_dma: OamDma::new(),
+            is_cgb,
+            background_palettes: new_palette_ram(),
+            object_palettes: new_palette_ram(),
+            vram_dma: VramDma::new(),
+            sgb: is_sgb.then(|| Box::new(Sgb::new())),
             needs_to_reset_div_register: false,
+            was_tima_written: false,
+            needs_to_start_serial_transfer: false,
+            needs_to_trigger_channel: [false; 4],
+            needs_to_reload_length: [false; 4],
+        }
+    }
+
+    /// Maps the boot rom and puts the io registers back to how they are when the GameBoy
+    /// is turned on, the boot rom will set them up by itself
+    pub(crate) fn load_boot_rom(&mut self, boot_rom: &[u8]) -> Result<(), BusError> {
+        if boot_rom.len() != BOOT_ROM_SIZE {
+            return Err(BusError::InvalidBootRomSize(boot_rom.len()));
+        }
+
+        self.boot_rom = Some(vector_to_array(boot_rom.to_vec()));
+        self.io = new_io_before_boot();
+
+        Ok(())
+    }
+}
+
+/// Reads the whole rom file
+pub(crate) fn read_rom_file(rom_path: &str) -> Result<Vec<u8>, BusError> {
+    let mut rom: Vec<u8> = Vec::new();
+
+    let mut rom_file = match File::open(rom_path) {
+        Ok(rom_file) => rom_file,
+        Err(_) => return Err(BusError::CouldNotFindRom),
+    };
+
+    match rom_file.read_to_end(&mut rom) {
+        Ok(_) => {}
+        Err(_) => return Err(BusError::CouldNotReadRom),
+    }
+
+    Ok(rom)
+}
+
+// CPU accesses
+impl Bus {
+    /// A read done by the CPU, some memory can't be accessed depending on what the rest of
+    /// the hardware is doing, in that case it reads as 0xFF
+    pub(crate) fn cpu_read(&self, address: u16) -> u8 {
+        match self.is_blocked_for_cpu(address) {
+            true => 0xFF,
+            false => self.read(address),
+        }
+    }
+
+    /// A write done by the CPU, writes to memory that can't be accessed are ignored
+    pub(crate) fn cpu_write(&mut self, address: u16, value: u8) {
+        if !self.is_blocked_for_cpu(address) {
+            self.write(address, value);
+        }
+    }
+
+    /// - While an OAM DMA transfer runs, the CPU can only use HRAM and the io registers
+    /// - While the GPU is drawing it owns VRAM
+    /// - While the GPU is looking for sprites or drawing it owns OAM
+    fn is_blocked_for_cpu(&self, address: u16) -> bool {
+        if self.oam_dma.is_running() && address < 0xFF00 {
+            return true;
+        }
+
+        match address {
+            0x8000..=0x9FFF => self.gpu_state == GpuState::PixelTransfer,
+            0xFE00..=0xFE9F => matches!(
+                self.gpu_state,
+                GpuState::OamSearch | GpuState::PixelTransfer
+            ),
+            _ => false,
         }
     }
 }
@@ -108,15 +240,32 @@ impl Bus {
 impl Bus {
     pub fn read(&self, address: u16) -> u8 {
         match address {
-            0x0000..=0x3FFF => self.mbc.get_rom_section_0(address),
+            // The boot rom is mapped over the start of the cartridge while it runs
+            0x0000..=0x00FF => match &self.boot_rom {
+                Some(boot_rom) => boot_rom[address as usize],
+                None => self.mbc.get_rom_section_0(address),
+            },
+
+            0x0100..=0x3FFF => self.mbc.get_rom_section_0(address),
             0x4000..=0x7FFF => self.mbc.get_rom_section_1(address),
-            0x8000..=0x9FFF => self.video_ram[(address - 0x8000) as usize],
+            0x8000..=0x9FFF => self.video_ram[self.get_video_ram_index(address)],
             0xA000..=0xBFFF => self.mbc.get_external_ram(address - 0xA000),
-            0xC000..=0xDFFF => self.work_ram[(address - 0xC000) as usize],
-            0xE000..=0xFDFF => self.work_ram[(address - 0xE000) as usize],
+            0xC000..=0xDFFF => self.work_ram[self.get_work_ram_index(address)],
+            0xE000..=0xFDFF => self.work_ram[self.get_work_ram_index(address - 0x2000)],
             0xFE00..=0xFE9F => self.eom[(address - 0xFE00) as usize],
             0xFEA0..=0xFEFF => self.unusable_ram[(address - 0xFEA0) as usize],
-            0xFF00..=0xFF7F => self.io[(address - IO_START as u16) as usize],
+
+            JOYP => self.get_joyp(),
+
+            _ if self.is_cgb && is_cgb_register(address) => self.read_cgb_register(address),
+
+            // Some bits of the APU registers are write only, and they always read as 1
+            NR10..=NR52 => {
+                let offset = (address - NR10) as usize;
+                self.io[(address - IO_START as u16) as usize] | APU_READ_MASKS[offset]
+            }
+
+            0xFF01..=0xFF7F => self.io[(address - IO_START as u16) as usize],
             0xFF80..=0xFFFE => self.high_ram[(address - 0xFF80) as usize],
             0xFFFF => self.ie,
         }
@@ -125,23 +274,99 @@ impl Bus {
     pub fn write(&mut self, address: u16, value: u8) {
         match address {
             DMA => {
-                self.needs_to_dispatch_oam_dma = true;
+                self.oam_dma.request(value);
                 self.io[0x46] = value;
             }
 
+            // Only the bits that select the group of buttons can be written, selecting
+            // another group can make a line go low, just like pressing a button
+            JOYP => {
+                self.write_joyp_to_sgb(value);
+
+                let previous_joyp = self.get_joyp();
+                self.io[0x00] = 0b11000000 | (value & 0b00110000);
+                self.request_joypad_interrupt(previous_joyp);
+            }
+
+            // Once the boot rom is unmapped there's no way to map it back
+            BOOT => {
+                if value != 0 {
+                    self.boot_rom = None;
+                }
+
+                self.io[0x50] = value;
+            }
+
+            // The lower 3 bits are set by the GPU, and bit 7 always reads as 1
+            STAT => {
+                self.io[0x41] = 0b10000000 | (value & 0b01111000) | (self.io[0x41] & 0b00000111);
+            }
+
+            // Only the clock select and transfer start bits exist, the rest read as 1
+            SC => {
+                self.needs_to_start_serial_transfer = true;
+                self.io[0x02] = value | 0b01111110;
+            }
+
+            _ if self.is_cgb && is_cgb_register(address) => self.write_cgb_register(address, value),
+
             DIV => {
                 self.needs_to_reset_div_register = true;
                 self.io[0x04] = 0;
             }
 
+            TIMA => {
+                self.was_tima_written = true;
+                self.io[0x05] = value;
+            }
+
+            // Only the power bit can be written, the rest is set by the APU. Turning the
+            // APU off clears all of its registers
+            NR52 => {
+                if !value.get_bit(7) {
+                    self.io[(NR10 - IO_START as u16) as usize..(NR52 - IO_START as u16) as usize]
+                        .fill(0);
+                }
+
+                self.io[0x26].set_bit(7, value.get_bit(7));
+            }
+
+            // While the APU is off, its registers can't be written to
+            NR10..=NR51 if !self.io[0x26].get_bit(7) => {}
+
+            NR11 | NR21 | NR31 | NR41 => {
+                self.needs_to_reload_length[((address - NR11) / 5) as usize] = true;
+                self.io[(address - IO_START as u16) as usize] = value;
+            }
+
+            NR14 | NR24 | NR34 | NR44 => {
+                if value.get_bit(7) {
+                    self.needs_to_trigger_channel[((address - NR14) / 5) as usize] = true;
+                }
+
+                self.io[(address - IO_START as u16) as usize] = value;
+            }
+
             0x0000..=0x7FFF => self.mbc.signal_rom_write(address, value),
-            0x8000..=0x9FFF => self.video_ram[(address - 0x8000) as usize] = value,
+            0x8000..=0x9FFF => {
+                let index = self.get_video_ram_index(address);
+                self.video_ram[index] = value;
+            }
+
             0xA000..=0xBFFF => self.mbc.set_external_ram(address - 0xA000, value),
-            0xC000..=0xDFFF => self.work_ram[(address - 0xC000) as usize] = value,
-            0xE000..=0xFDFF => self.work_ram[(address - 0xE000) as usize] = value,
+            0xC000..=0xDFFF => {
+                let index = self.get_work_ram_index(address);
+                self.work_ram[index] = value;
+            }
+
+            0xE000..=0xFDFF => {
+                let index = self.get_work_ram_index(address - 0x2000);
+                self.work_ram[index] = value;
+            }
+
             0xFE00..=0xFE9F => self.eom[(address - 0xFE00) as usize] = value,
             0xFEA0..=0xFEFF => self.unusable_ram[(address - 0xFEA0) as usize] = value,
-            0xFF00..=0xFF7F => self.io[(address - IO_START as u16) as usize] = value,
+            0xFF01..=0xFF7F => self.io[(address - IO_START as u16) as usize] = value,
             0xFF80..=0xFFFE => self.high_ram[(address - 0xFF80) as usize] = value,
             0xFFFF => self.ie = value,
         };
@@ -149,44 +374,183 @@ impl Bus {
 }
 
 impl Bus {
-    /// Returns the byte X times after the `PC` register
-    pub(crate) fn next(&self, offset: u16, registers: &Registers) -> u8 {
-        self.read(registers.pc.wrapping_add(offset))
+    /// Bit 4 selects the directional pad and bit 5 the other buttons, a group is selected
+    /// when its bit is 0. The lower nibble has the buttons of the selected groups, where 0
+    /// means pressed
+    fn get_joyp(&self) -> u8 {
+        let select = self.io[0x00] & 0b00110000;
+        let mut pressed = 0;
+
+        // With the SGB multiplayer, JOYP reads the id of the joypad when no group is
+        // selected
+        if let Some(joypad_id) = self.get_sgb_joypad_id() {
+            if select == 0b00110000 {
+                return 0b11000000 | select | joypad_id;
+            }
+        }
+
+        let pressed_buttons = match self.is_first_sgb_player() {
+            true => self.pressed_buttons,
+            false => 0,
+        };
+
+        if !select.get_bit(4) {
+            pressed |= pressed_buttons & 0x0F;
+        }
+
+        if !select.get_bit(5) {
+            pressed |= pressed_buttons >> 4;
+        }
+
+        0b11000000 | select | (!pressed & 0x0F)
     }
 
-    /// Returns the byte after the `PC` register
-    pub(crate) fn next_one(&self, registers: &Registers) -> u8 {
-        self.next(1, registers)
+    /// Wheter or not a button of the selected groups is held down
+    pub(crate) fn is_selected_button_pressed(&self) -> bool {
+        self.get_joyp() & 0x0F != 0x0F
     }
 
-    /// Returns the next two bytes from the `PC` register in little endian format
-    pub(crate) fn next_two(&self, registers: &Registers) -> u16 {
-        merge_two_u8s_into_u16(self.next(2, registers), self.next(1, registers))
+    /// Called by the GameBoy every step with the state of the joypad
+    pub(crate) fn set_pressed_buttons(&mut self, pressed_buttons: u8) {
+        let previous_joyp = self.get_joyp();
+        self.pressed_buttons = pressed_buttons;
+        self.request_joypad_interrupt(previous_joyp);
+    }
+
+    /// The joypad interrupt is requested when any of the lower 4 bits of JOYP goes from
+    /// high to low
+    fn request_joypad_interrupt(&mut self, previous_joyp: u8) {
+        if previous_joyp & !self.get_joyp() & 0x0F != 0 {
+            self.write(IF, self.read(IF) | 0b00010000); // Enabling joypad interrupt
+        }
     }
 }
 
-impl Bus {
-    pub(crate) fn dispatch_oam_transfer(&mut self) {
-        let oam_dma_start = (self.read(DMA) as u16) << 8;
-        let oam_dma_end = oam_dma_start | 0x9F;
-        let difference = oam_dma_end - oam_dma_start;
+impl SaveState for Bus {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.mbc.save_state(writer);
+        writer.write_bytes(&self.video_ram);
+        writer.write_bytes(&self.work_ram);
+        writer.write_bytes(&self.eom);
+        writer.write_bytes(&self.unusable_ram);
+        writer.write_bytes(&self.io);
+        writer.write_bytes(&self.high_ram);
+        writer.write_u8(self.ie);
+        writer.write_u8(self.pressed_buttons);
+
+        writer.write_bool(self.boot_rom.is_some());
+        if let Some(boot_rom) = &self.boot_rom {
+            writer.write_bytes(boot_rom);
+        }
+
+        writer.write_u8(self.gpu_state as u8);
+        self.oam_dma.save_state(writer);
+        writer.write_bool(self.is_cgb);
+        writer.write_bytes(&self.background_palettes);
+        writer.write_bytes(&self.object_palettes);
+        self.vram_dma.save_state(writer);
+
+        writer.write_bool(self.sgb.is_some());
+        if let Some(sgb) = &self.sgb {
+            sgb.save_state(writer);
+        }
+        writer.write_bool(self.needs_to_reset_div_register);
+        writer.write_bool(self.was_tima_written);
+        writer.write_bool(self.needs_to_start_serial_transfer);
+
+        for i in 0..4 {
+            writer.write_bool(self.needs_to_trigger_channel[i]);
+            writer.write_bool(self.needs_to_reload_length[i]);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.mbc.load_state(reader)?;
+        reader.read_bytes(&mut self.video_ram)?;
+        reader.read_bytes(&mut self.work_ram)?;
+        reader.read_bytes(&mut self.eom)?;
+        reader.read_bytes(&mut self.unusable_ram)?;
+        reader.read_bytes(&mut self.io)?;
+        reader.read_bytes(&mut self.high_ram)?;
+        self.ie = reader.read_u8()?;
+        self.pressed_buttons = reader.read_u8()?;
+
+        self.boot_rom = match reader.read_bool()? {
+            true => {
+                let mut boot_rom = [0u8; BOOT_ROM_SIZE];
+                reader.read_bytes(&mut boot_rom)?;
+                Some(boot_rom)
+            }
+            false => None,
+        };
 
-        for i in 0..difference {
-            self.write(0xFE00 + i, self.read(oam_dma_start + i));
+        self.gpu_state = GpuState::from_state(reader.read_u8()?)?;
+        self.oam_dma.load_state(reader)?;
+        self.is_cgb = reader.read_bool()?;
+        reader.read_bytes(&mut self.background_palettes)?;
+        reader.read_bytes(&mut self.object_palettes)?;
+        self.vram_dma.load_state(reader)?;
+
+        // The save state is always for the same game, so the SGB is there only if it was
+        // there when the state was saved
+        match (reader.read_bool()?, &mut self.sgb) {
+            (true, Some(sgb)) => sgb.load_state(reader)?,
+            (false, None) => {}
+            _ => return Err(SaveStateError::Corrupted),
         }
+        self.needs_to_reset_div_register = reader.read_bool()?;
+        self.was_tima_written = reader.read_bool()?;
+        self.needs_to_start_serial_transfer = reader.read_bool()?;
+
+        for i in 0..4 {
+            self.needs_to_trigger_channel[i] = reader.read_bool()?;
+            self.needs_to_reload_length[i] = reader.read_bool()?;
+        }
+
+        Ok(())
     }
 }
 
-/// Creates a specific MBC cartridge based on the header data from the rom
-pub(crate) fn new_mbc(rom: Vec<u8>) -> Box<dyn Mbc> {
-    let mbc_type = *rom.get(0x147).unwrap_or(&0) as usize;
+/// Creates a specific MBC cartridge based on the header data from the rom, and checks
+/// that the header makes sense, otherwise the game would just run as garbage
+pub(crate) fn new_mbc(rom: Vec<u8>, header: &CartridgeHeader) -> Result<Box<dyn Mbc>, BusError> {
+    if rom.len() < HEADER_END {
+        return Err(BusError::RomTooSmall { size: rom.len() });
+    }
+
+    // The rom size byte goes from 0 to 8, anything else is not a real cartridge
+    if header.rom_size_byte > 8 || header.rom_size() != rom.len() {
+        return Err(BusError::RomSizeMismatch {
+            header_size: header.rom_size(),
+            actual_size: rom.len(),
+        });
+    }
+
+    match header.cartridge_type {
+        // Without an MBC there's no way to switch banks, so the rom can only be 32 KiB
+        0x00 | 0x08..=0x09 if header.rom_size_byte != 0 => {
+            Err(BusError::RomTooBigWithoutMbc { size: rom.len() })
+        }
+
+        0x00..=0x03 | 0x05..=0x06 | 0x08..=0x09 | 0x0F..=0x13 | 0x19..=0x1E => {
+            Ok(new_mbc_unchecked(rom, header))
+        }
 
-    match mbc_type {
-        0x00 => Box::new(NoMbc::new(rom)),
-        0x01..=0x03 => Box::new(Mbc1::new(rom)),
-        0x0F..=0x13 => Box::new(Mbc3::new(rom)),
+        cartridge_type => Err(BusError::UnsupportedCartridgeType(cartridge_type)),
+    }
+}
 
-        _ => Box::new(NoMbc::new(rom)),
+/// Like `new_mbc` but it never fails, unknown cartridge types are treated as if they had
+/// no MBC. This is useful for homebrew roms, which often don't have a proper header
+pub(crate) fn new_mbc_unchecked(rom: Vec<u8>, header: &CartridgeHeader) -> Box<dyn Mbc> {
+    match header.cartridge_type {
+        0x00 | 0x08..=0x09 => Box::new(NoMbc::new(rom, header)),
+        0x01..=0x03 => Box::new(Mbc1::new(rom, header)),
+        0x05..=0x06 => Box::new(Mbc2::new(rom, header)),
+        0x0F..=0x13 => Box::new(Mbc3::new(rom, header)),
+        0x19..=0x1E => Box::new(Mbc5::new(rom, header)),
+
+        _ => Box::new(NoMbc::new(rom, header)),
     }
 }
 
@@ -194,6 +558,37 @@ pub(crate) fn new_mbc(rom: Vec<u8>) -> Box<dyn Mbc> {
 pub enum BusError {
     CouldNotFindRom,
     CouldNotReadRom,
+
+    /// The rom is not even big enough to contain the header
+    RomTooSmall {
+        size: usize,
+    },
+
+    /// The size in the rom header is not the actual size of the rom
+    RomSizeMismatch {
+        header_size: usize,
+        actual_size: usize,
+    },
+
+    /// The cartridge type in the header is not one we know how to emulate
+    UnsupportedCartridgeType(u8),
+
+    /// The cartridge type says there's no MBC, but the rom is bigger than 32 KiB
+    RomTooBigWithoutMbc {
+        size: usize,
+    },
+
+    /// The DMG boot rom is always 256 bytes
+    InvalidBootRomSize(usize),
+
+    /// The cartridge has no battery, so there's nothing to load the save data into
+    NoBattery,
+
+    /// The save data is smaller than the cartridge's external ram
+    SaveDataTooSmall {
+        expected: usize,
+        found: usize,
+    },
 }
 
 impl Error for BusError {}
@@ -202,6 +597,36 @@ impl Display for BusError {
         match self {
             Self::CouldNotFindRom => write!(f, "could not find rom"),
             Self::CouldNotReadRom => write!(f, "could not read rom"),
+            Self::RomTooSmall { size } => write!(
+                f,
+                "rom is {} bytes, which is too small to contain a header",
+                size
+            ),
+            Self::RomSizeMismatch {
+                header_size,
+                actual_size,
+            } => write!(
+                f,
+                "rom header says the rom is {} bytes but it's {} bytes",
+                header_size, actual_size
+            ),
+            Self::UnsupportedCartridgeType(cartridge_type) => {
+                write!(f, "unsupported cartridge type {:#04x}", cartridge_type)
+            }
+            Self::RomTooBigWithoutMbc { size } => write!(
+                f,
+                "rom is {} bytes, but a cartridge without an MBC can only have 32768 bytes",
+                size
+            ),
+            Self::InvalidBootRomSize(size) => {
+                write!(f, "boot rom is {} bytes but it should be 256 bytes", size)
+            }
+            Self::NoBattery => write!(f, "the cartridge has no battery"),
+            Self::SaveDataTooSmall { expected, found } => write!(
+                f,
+                "save data is too small, expected {} bytes but found {}",
+                expected, found
+            ),
         }
     }
 }
@@ -211,7 +636,7 @@ const fn new_io() -> [u8; IO_SIZE] {
     // IO default values
     // Yes, there's no better way to do this
     let mut io = [0u8; IO_SIZE];
-    io[0x00] = 0xCF; io[0x02] = 0x7E; io[0x04] = 0x18; io[0x07] = 0xF8; io[0x0F] = 0xE1;
+    io[0x00] = 0xCF; io[0x02] = 0x7E; io[0x04] = 0xAB; io[0x07] = 0xF8; io[0x0F] = 0xE1;
     io[0x10] = 0x80; io[0x11] = 0xBF; io[0x12] = 0xF3; io[0x13] = 0xFF; io[0x14] = 0xBF;
     io[0x16] = 0x3F; io[0x18] = 0xFF; io[0x19] = 0xBF; io[0x1A] = 0x7F; io[0x1B] = 0xFF;
     io[0x1C] = 0x9F; io[0x1D] = 0xFF; io[0x1E] = 0xBF; io[0x20] = 0xFF; io[0x23] = 0xBF;
@@ -224,10 +649,43 @@ const fn new_io() -> [u8; IO_SIZE] {
     io
 }
 
+/// The CGB boot rom leaves its own registers cleared, so that the game starts at normal
+/// speed with the first banks selected
+const fn new_cgb_io() -> [u8; IO_SIZE] {
+    let mut io = new_io();
+    io[0x4D] = 0x7E;
+    io[0x4F] = 0x00;
+    io[0x68] = 0x00;
+    io[0x6A] = 0x00;
+    io[0x70] = 0x00;
+
+    io
+}
+
+/// Before the boot rom runs almost every io register is 0, only the unused bits are set
+const fn new_io_before_boot() -> [u8; IO_SIZE] {
+    let mut io = [0u8; IO_SIZE];
+    io[0x00] = 0xCF;
+    io[0x0F] = 0xE0;
+
+    io
+}
+
+/// The bits of NR10 to NR52 that can't be read, they are always returned as 1
+const APU_READ_MASKS: [u8; (NR52 - NR10 + 1) as usize] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10 - NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // NR20 - NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30 - NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // NR40 - NR44
+    0x00, 0x00, 0x70, // NR50 - NR52
+];
+
+/// Anything after the first `SIZE` bytes is dropped, homebrew roms can be bigger than
+/// what their cartridge type can address
 pub(crate) fn vector_to_array<const SIZE: usize>(vec: Vec<u8>) -> [u8; SIZE] {
     let mut array = [0; SIZE];
 
-    for (i, &val) in vec.iter().enumerate() {
+    for (i, &val) in vec.iter().take(SIZE).enumerate() {
         array[i] = val;
     }
 
@@ -236,7 +694,7 @@ pub(crate) fn vector_to_array<const SIZE: usize>(vec: Vec<u8>) -> [u8; SIZE] {
 
 // Functions used by the MBCn files
 
-pub(super) const EXTERNAL_RAM_BANK_SIZE: usize = 8000;
+pub(super) const EXTERNAL_RAM_BANK_SIZE: usize = 0x2000;
 pub(super) const ROM_BANK_SIZE: usize = 0x4000;
 
 /// Calculates the new rom address based on the address the game tells us and the rom
@@ -249,24 +707,32 @@ pub(super) fn calculate_ram_address(ram_size: usize, address: u16, bank: usize)
     (address as usize + EXTERNAL_RAM_BANK_SIZE * bank) % ram_size
 }
 
-/// Fetches the rom rize using the rom header data
-pub(super) fn get_rom_size(rom: &[u8]) -> usize {
-    let rom_size_byte = *rom.get(0x148).unwrap_or(&0) as usize;
-
-    // This formula is from the pandocs
-    0x8000 * (1 << rom_size_byte)
+/// Returns a copy of the external ram if the cartridge has a battery
+pub(super) fn get_save_data(has_battery: bool, external_ram: &[u8]) -> Option<Vec<u8>> {
+    match has_battery {
+        true => Some(external_ram.to_vec()),
+        false => None,
+    }
 }
 
-/// Fetches the ram rize using the rom header data
-pub(super) fn get_ram_size(rom: &[u8]) -> usize {
-    let ram_size_byte = *rom.get(0x149).unwrap_or(&0) as usize;
-
-    // This formula is from the pandocs
-    match ram_size_byte {
-        2 => EXTERNAL_RAM_BANK_SIZE,
-        3 => EXTERNAL_RAM_BANK_SIZE * 4,
-        4 => EXTERNAL_RAM_BANK_SIZE * 16,
-        5 => EXTERNAL_RAM_BANK_SIZE * 8,
-        _ => 0,
+/// Copies the save data in the external ram. Some emulators put extra data at the end of
+/// the file (like the MBC3 clock), so we ignore everything after the ram
+pub(super) fn load_save_data(
+    has_battery: bool,
+    external_ram: &mut [u8],
+    data: &[u8],
+) -> Result<(), BusError> {
+    if !has_battery {
+        return Err(BusError::NoBattery);
+    }
+
+    if data.len() < external_ram.len() {
+        return Err(BusError::SaveDataTooSmall {
+            expected: external_ram.len(),
+            found: data.len(),
+        });
     }
+
+    external_ram.copy_from_slice(&data[..external_ram.len()]);
+    Ok(())
 }
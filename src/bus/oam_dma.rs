@@ -0,0 +1,115 @@
+//! Writing to DMA copies 160 bytes from `XX00` to OAM, where `XX` is the value written.
+//! This takes 160 cycles, one byte per cycle, after a cycle where the transfer starts up.
+//!
+//! While the transfer runs the DMA controller owns the bus, so the CPU can only use the
+//! memory that sits on its own internal bus: HRAM and the io registers. Everything else
+//! reads as 0xFF and can't be written. The PPU can't see OAM either, it reads 0xFF too.
+//!
+//! This is a really good resource: https://gbdev.io/pandocs/OAM_DMA_Transfer.html
+
+use crate::{
+    consts::bus::EOM_SIZE,
+    save_state::{SaveState, SaveStateError, StateReader, StateWriter},
+};
+
+use super::Bus;
+
+#[derive(Default)]
+pub(crate) struct OamDma {
+    /// The game wrote to DMA during this cycle, the transfer starts up the next one
+    requested_source: Option<u16>,
+
+    /// The transfer is starting up, bytes are copied from the next cycle
+    starting_source: Option<u16>,
+
+    /// The address the running transfer copies from
+    source: u16,
+
+    /// How many bytes of the running transfer have been copied, `None` if no transfer is
+    /// running
+    copied_bytes: Option<u8>,
+}
+
+impl OamDma {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Called when DMA is written. Sources above 0xDFFF can't reach OAM or io, the DMA
+    /// controller sees work ram there instead, like the echo ram
+    pub(crate) fn request(&mut self, value: u8) {
+        let source = (value as u16) << 8;
+
+        self.requested_source = Some(match source {
+            0xE000..=0xFFFF => source - 0x2000,
+            _ => source,
+        });
+    }
+
+    pub(crate) fn is_running(&self) -> bool {
+        self.copied_bytes.is_some()
+    }
+}
+
+impl Bus {
+    /// This needs to be called once every cycle. Writing to DMA while a transfer is
+    /// running restarts it, but the old one keeps going while the new one starts up
+    pub(crate) fn tick_oam_dma(&mut self) {
+        if let Some(source) = self.oam_dma.starting_source.take() {
+            self.oam_dma.source = source;
+            self.oam_dma.copied_bytes = Some(0);
+        }
+
+        // The transfer is over as soon as the last byte is copied
+        if let Some(copied_bytes) = self.oam_dma.copied_bytes {
+            let index = copied_bytes as usize;
+            self.eom[index] = self.read(self.oam_dma.source + index as u16);
+
+            self.oam_dma.copied_bytes = match index + 1 {
+                EOM_SIZE => None,
+                _ => Some(copied_bytes + 1),
+            };
+        }
+
+        self.oam_dma.starting_source = self.oam_dma.requested_source.take();
+    }
+
+    /// A read of OAM done by the PPU, OAM is not connected to it during an OAM DMA
+    /// transfer
+    pub(crate) fn ppu_read_oam(&self, address: u16) -> u8 {
+        match self.oam_dma.is_running() {
+            true => 0xFF,
+            false => self.read(address),
+        }
+    }
+}
+
+impl SaveState for OamDma {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.requested_source.is_some());
+        writer.write_u16(self.requested_source.unwrap_or_default());
+        writer.write_bool(self.starting_source.is_some());
+        writer.write_u16(self.starting_source.unwrap_or_default());
+        writer.write_u16(self.source);
+        writer.write_bool(self.copied_bytes.is_some());
+        writer.write_u8(self.copied_bytes.unwrap_or_default());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        let has_requested_source = reader.read_bool()?;
+        let requested_source = reader.read_u16()?;
+        self.requested_source = has_requested_source.then_some(requested_source);
+
+        let has_starting_source = reader.read_bool()?;
+        let starting_source = reader.read_u16()?;
+        self.starting_source = has_starting_source.then_some(starting_source);
+
+        self.source = reader.read_u16()?;
+
+        let is_running = reader.read_bool()?;
+        let copied_bytes = reader.read_u8()?;
+        self.copied_bytes = is_running.then_some(copied_bytes);
+
+        Ok(())
+    }
+}
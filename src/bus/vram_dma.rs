@@ -0,0 +1,165 @@
+//! The GameBoy Color can copy data to VRAM much faster than the CPU, in blocks of 16
+//! bytes. HDMA1 and HDMA2 are the source, HDMA3 and HDMA4 the destination in VRAM, and
+//! writing to HDMA5 starts the transfer. There are two kinds of transfers:
+//! - General purpose DMA copies everything at once
+//! - HBlank DMA copies a block at the start of every HBlank
+//!
+//! A block takes 8 cycles to copy (16 at double speed), and the CPU doesn't run while a
+//! block is being copied, so a general purpose DMA stalls it for the whole transfer.
+//!
+//! This is a really good resource: https://gbdev.io/pandocs/CGB_Registers.html#lcd-vram-dma-transfers
+
+use crate::{
+    common::{merge_two_u8s_into_u16, Bit},
+    gpu::GpuState,
+    save_state::{SaveState, SaveStateError, StateReader, StateWriter},
+};
+
+use super::Bus;
+
+/// Every transfer copies data in blocks of this many bytes
+const BLOCK_SIZE: u8 = 16;
+
+#[derive(Default)]
+pub(crate) struct VramDma {
+    /// Where the next byte is copied from
+    source: u16,
+
+    /// Where the next byte is copied to, this is always in VRAM
+    destination: u16,
+
+    /// How many blocks are left to copy, including the one being copied
+    remaining_blocks: u8,
+
+    /// Whether or not a transfer was started and it's not done or cancelled yet
+    is_active: bool,
+
+    /// Whether the transfer copies a block every HBlank or everything at once
+    is_hblank_dma: bool,
+
+    /// How many bytes of the current block have been copied, `None` if no block is being
+    /// copied
+    copied_bytes: Option<u8>,
+}
+
+impl VramDma {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// The CPU is stalled while a block is being copied
+    pub(crate) fn is_copying(&self) -> bool {
+        self.copied_bytes.is_some()
+    }
+}
+
+impl Bus {
+    /// Called when HDMA5 is written, the lower 7 bits are the number of blocks to copy
+    /// minus one and bit 7 selects the HBlank DMA. Writing with bit 7 clear while an
+    /// HBlank DMA is running cancels it instead
+    pub(super) fn start_vram_dma(&mut self, value: u8) {
+        if self.vram_dma.is_active && self.vram_dma.is_hblank_dma && !value.get_bit(7) {
+            self.vram_dma.is_active = false;
+            return;
+        }
+
+        self.vram_dma.source = merge_two_u8s_into_u16(self.io[0x51], self.io[0x52]) & 0xFFF0;
+        self.vram_dma.destination =
+            0x8000 | (merge_two_u8s_into_u16(self.io[0x53], self.io[0x54]) & 0x1FF0);
+        self.vram_dma.remaining_blocks = (value & 0b01111111) + 1;
+        self.vram_dma.is_active = true;
+        self.vram_dma.is_hblank_dma = value.get_bit(7);
+
+        // An HBlank DMA started during HBlank (or with the LCD off) copies the first
+        // block right away
+        if !self.vram_dma.is_hblank_dma || self.gpu_state == GpuState::HBlank {
+            self.vram_dma.copied_bytes = Some(0);
+        }
+    }
+
+    /// Called by the GPU when HBlank starts
+    pub(crate) fn on_hblank_vram_dma(&mut self) {
+        if self.vram_dma.is_active && self.vram_dma.is_hblank_dma && !self.vram_dma.is_copying() {
+            self.vram_dma.copied_bytes = Some(0);
+        }
+    }
+
+    /// This needs to be called once every cycle. The transfer always copies 16 bytes in
+    /// the same time, so at double speed it copies half as many bytes every cycle
+    pub(crate) fn tick_vram_dma(&mut self) {
+        let Some(mut copied_bytes) = self.vram_dma.copied_bytes else {
+            return;
+        };
+
+        let bytes_per_cycle = match self.is_double_speed() {
+            true => 1,
+            false => 2,
+        };
+
+        for _ in 0..bytes_per_cycle {
+            let value = self.read(self.vram_dma.source);
+            let index = self.get_video_ram_index(self.vram_dma.destination);
+            self.video_ram[index] = value;
+
+            // The destination wraps around in VRAM
+            self.vram_dma.source = self.vram_dma.source.wrapping_add(1);
+            self.vram_dma.destination =
+                0x8000 | (self.vram_dma.destination.wrapping_add(1) & 0x1FFF);
+            copied_bytes += 1;
+        }
+
+        if copied_bytes < BLOCK_SIZE {
+            self.vram_dma.copied_bytes = Some(copied_bytes);
+            return;
+        }
+
+        self.vram_dma.copied_bytes = None;
+        self.vram_dma.remaining_blocks -= 1;
+
+        match self.vram_dma.remaining_blocks {
+            0 => self.vram_dma.is_active = false,
+
+            // A general purpose DMA goes on with the next block
+            _ if !self.vram_dma.is_hblank_dma => self.vram_dma.copied_bytes = Some(0),
+            _ => {}
+        }
+    }
+
+    /// The lower 7 bits are the number of blocks left minus one, bit 7 is clear while the
+    /// transfer is active. Once it's done this reads as 0xFF, and after it's cancelled bit
+    /// 7 is set but the lower bits still say how much was left
+    pub(super) fn read_hdma5(&self) -> u8 {
+        let remaining = self.vram_dma.remaining_blocks.wrapping_sub(1) & 0b01111111;
+
+        match self.vram_dma.is_active {
+            true => remaining,
+            false => 0b10000000 | remaining,
+        }
+    }
+}
+
+impl SaveState for VramDma {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.source);
+        writer.write_u16(self.destination);
+        writer.write_u8(self.remaining_blocks);
+        writer.write_bool(self.is_active);
+        writer.write_bool(self.is_hblank_dma);
+        writer.write_bool(self.copied_bytes.is_some());
+        writer.write_u8(self.copied_bytes.unwrap_or_default());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.source = reader.read_u16()?;
+        self.destination = reader.read_u16()?;
+        self.remaining_blocks = reader.read_u8()?;
+        self.is_active = reader.read_bool()?;
+        self.is_hblank_dma = reader.read_bool()?;
+
+        let is_copying = reader.read_bool()?;
+        let copied_bytes = reader.read_u8()?;
+        self.copied_bytes = is_copying.then_some(copied_bytes);
+
+        Ok(())
+    }
+}
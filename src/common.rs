@@ -27,3 +27,17 @@ impl Bit for u8 {
         }
     }
 }
+
+impl Bit for u16 {
+    fn get_bit(&self, offset: u16) -> bool {
+        (self >> offset) & 0b00000001 != 0
+    }
+
+    fn set_bit(&mut self, offset: u16, value: bool) {
+        if value {
+            *self |= 1 << offset;
+        } else {
+            *self &= !(1 << offset);
+        }
+    }
+}
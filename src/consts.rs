@@ -4,9 +4,13 @@ pub mod bus {
     pub const IO_SIZE: usize = 128;
     pub const IO_START: usize = 0xFF00;
     pub const VIDEO_RAM_SIZE: usize = 8192;
-    pub const WORK_RAM_SIZE: usize = 8192;
+    pub const VIDEO_RAM_BANKS: usize = 2;
+    pub const WORK_RAM_BANK_SIZE: usize = 4096;
+    pub const WORK_RAM_BANKS: usize = 8;
     pub const UNUSABLE_RAM_SIZE: usize = 96;
     pub const DMA: u16 = 0xFF46;
+    pub const BOOT: u16 = 0xFF50;
+    pub const BOOT_ROM_SIZE: usize = 256;
 }
 
 pub mod gpu {
@@ -29,6 +33,9 @@ pub mod cpu {
     pub const TIMA: u16 = 0xFF05;
     pub const TMA: u16 = 0xFF06;
     pub const TAC: u16 = 0xFF07;
+
+    /// The number of dots (T-cycles) the GameBoy runs in a second
+    pub const CLOCK_SPEED: u32 = 4_194_304;
 }
 
 pub mod display {
@@ -36,6 +43,60 @@ pub mod display {
     pub const DISPLAY_SIZE_Y: usize = 144;
 }
 
+pub mod serial {
+    pub const SB: u16 = 0xFF01;
+    pub const SC: u16 = 0xFF02;
+}
+
+pub mod cgb {
+    pub const KEY1: u16 = 0xFF4D;
+    pub const VBK: u16 = 0xFF4F;
+    pub const HDMA1: u16 = 0xFF51;
+    pub const HDMA2: u16 = 0xFF52;
+    pub const HDMA3: u16 = 0xFF53;
+    pub const HDMA4: u16 = 0xFF54;
+    pub const HDMA5: u16 = 0xFF55;
+    pub const BCPS: u16 = 0xFF68;
+    pub const BCPD: u16 = 0xFF69;
+    pub const OCPS: u16 = 0xFF6A;
+    pub const OCPD: u16 = 0xFF6B;
+    pub const SVBK: u16 = 0xFF70;
+
+    /// Every palette has 4 colors of 2 bytes each, and there are 8 of them
+    pub const PALETTE_RAM_SIZE: usize = 64;
+}
+
+pub mod sgb {
+    pub const SGB_FRAME_SIZE_X: usize = 256;
+    pub const SGB_FRAME_SIZE_Y: usize = 224;
+}
+
 pub mod joypad {
     pub const JOYP: u16 = 0xFF00;
 }
+
+pub mod apu {
+    pub const NR10: u16 = 0xFF10;
+    pub const NR11: u16 = 0xFF11;
+    pub const NR12: u16 = 0xFF12;
+    pub const NR13: u16 = 0xFF13;
+    pub const NR14: u16 = 0xFF14;
+    pub const NR21: u16 = 0xFF16;
+    pub const NR22: u16 = 0xFF17;
+    pub const NR23: u16 = 0xFF18;
+    pub const NR24: u16 = 0xFF19;
+    pub const NR30: u16 = 0xFF1A;
+    pub const NR31: u16 = 0xFF1B;
+    pub const NR32: u16 = 0xFF1C;
+    pub const NR33: u16 = 0xFF1D;
+    pub const NR34: u16 = 0xFF1E;
+    pub const NR41: u16 = 0xFF20;
+    pub const NR42: u16 = 0xFF21;
+    pub const NR43: u16 = 0xFF22;
+    pub const NR44: u16 = 0xFF23;
+    pub const NR50: u16 = 0xFF24;
+    pub const NR51: u16 = 0xFF25;
+    pub const NR52: u16 = 0xFF26;
+    pub const WAVE_RAM_START: u16 = 0xFF30;
+    pub const WAVE_RAM_SIZE: usize = 16;
+}
@@ -0,0 +1,119 @@
+//! Every memory access of the CPU takes a cycle, and the rest of the GameBoy keeps going
+//! while the CPU works. By default the other pieces of hardware are ticked after the whole
+//! instruction is done, which is fast but it means that something like a write to a
+//! register in the middle of an instruction happens a few cycles too early. In the cycle
+//! accurate mode the CPU goes through `TickingBus` instead, which ticks everything else
+//! before every access.
+
+use crate::{
+    apu::Apu,
+    bus::Bus,
+    common::merge_two_u8s_into_u16,
+    gpu::{pixel_transfer::Layers, Gpu},
+    registers::Registers,
+    serial::Serial,
+    timer::Timer,
+};
+
+/// How the CPU sees memory
+pub trait CpuBus {
+    /// A memory read done by the CPU, this takes a cycle
+    fn read(&mut self, address: u16) -> u8;
+
+    /// A memory write done by the CPU, this takes a cycle
+    fn write(&mut self, address: u16, value: u8);
+
+    /// A cycle in which the CPU does something internally and does not access memory
+    fn tick(&mut self);
+
+    /// The bus itself, this is used for the registers the CPU checks internally, like IF
+    /// and IE, which doesn't take any time
+    fn bus_mut(&mut self) -> &mut Bus;
+
+    /// Returns the byte X times after the `PC` register
+    fn next(&mut self, offset: u16, registers: &Registers) -> u8 {
+        self.read(registers.pc.wrapping_add(offset))
+    }
+
+    /// Returns the byte after the `PC` register
+    fn next_one(&mut self, registers: &Registers) -> u8 {
+        self.next(1, registers)
+    }
+
+    /// Returns the next two bytes from the `PC` register in little endian format, the low
+    /// byte is read first like the real CPU does
+    fn next_two(&mut self, registers: &Registers) -> u16 {
+        let low = self.next(1, registers);
+        let high = self.next(2, registers);
+
+        merge_two_u8s_into_u16(high, low)
+    }
+}
+
+/// Without the cycle accurate mode the CPU uses the bus directly, the time taken by the
+/// instruction is then given by the cycles it returns
+impl CpuBus for Bus {
+    fn read(&mut self, address: u16) -> u8 {
+        self.cpu_read(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.cpu_write(address, value);
+    }
+
+    fn tick(&mut self) {}
+
+    fn bus_mut(&mut self) -> &mut Bus {
+        self
+    }
+}
+
+/// Holds everything that needs to be ticked while the CPU runs
+pub(crate) struct TickingBus<'a> {
+    pub(crate) bus: &'a mut Bus,
+    pub(crate) gpu: &'a mut Gpu,
+    pub(crate) layers: &'a mut Layers,
+    pub(crate) apu: &'a mut Apu,
+    pub(crate) serial: &'a mut Serial,
+    pub(crate) timer: &'a mut Timer,
+
+    /// The number of cycles that were ticked so far
+    pub(crate) cycles: u8,
+}
+
+impl CpuBus for TickingBus<'_> {
+    fn read(&mut self, address: u16) -> u8 {
+        self.tick();
+        self.bus.cpu_read(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.tick();
+        self.bus.cpu_write(address, value);
+    }
+
+    fn tick(&mut self) {
+        self.bus.tick_oam_dma();
+        self.bus.tick_vram_dma();
+        self.timer.tick(self.bus);
+
+        // The GPU and the APU are ticked every dot, which is 1/4 of a cycle, or 1/2 of a
+        // cycle when the CPU runs at double speed
+        let dots = match self.bus.is_double_speed() {
+            true => 2,
+            false => 4,
+        };
+
+        for _ in 0..dots {
+            self.gpu.tick(self.layers, self.bus);
+            self.apu.tick(self.bus);
+        }
+
+        self.serial.tick(self.bus);
+        self.cycles += 1;
+    }
+
+    fn bus_mut(&mut self) -> &mut Bus {
+        self.bus
+    }
+}
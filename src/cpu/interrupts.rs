@@ -5,7 +5,7 @@ use crate::{
     registers::Registers,
 };
 
-use super::Cpu;
+use super::{cpu_bus::CpuBus, Cpu, Cycles};
 
 #[derive(Clone, Copy)]
 pub(crate) enum Interrupt {
@@ -21,63 +21,121 @@ pub(crate) enum Interrupt {
 
     /// Triggers when TIMA register overflows
     Timer = 2,
+
+    /// Triggers when a serial transfer ends
+    Serial = 3,
+
+    /// Triggers when a button of the selected groups gets pressed
+    Joypad = 4,
+}
+
+impl Interrupt {
+    /// When more than one interrupt is pending the one with the lowest bit wins, so VBlank
+    /// has the highest priority and Joypad the lowest
+    const BY_PRIORITY: [Interrupt; 5] = [
+        Interrupt::VBlank,
+        Interrupt::Stat,
+        Interrupt::Timer,
+        Interrupt::Serial,
+        Interrupt::Joypad,
+    ];
+
+    /// The interrupt with the highest priority from the pending ones, if any
+    fn highest_priority(pending_interrupts: u8) -> Option<Interrupt> {
+        Self::BY_PRIORITY
+            .into_iter()
+            .find(|&interrupt| pending_interrupts.get_bit(interrupt as u8))
+    }
+
+    /// The address the CPU jumps to when handling the interrupt
+    fn address(self) -> u16 {
+        match self {
+            Interrupt::VBlank => 0x40,
+            Interrupt::Stat => 0x48,
+            Interrupt::Timer => 0x50,
+            Interrupt::Serial => 0x58,
+            Interrupt::Joypad => 0x60,
+        }
+    }
 }
 
 impl Cpu {
     /// We start executing an interrupt when both the interrupt enable and interrupt flag
-    /// are enabled
-    pub(crate) fn execute_interrupts(&mut self, registers: &mut Registers, bus: &mut Bus) {
-        let interrupt_enable = bus.ie;
-        let interrupt_flag = bus.read(IF);
-
-        if interrupt_enable.get_bit(0) && interrupt_flag.get_bit(0) {
-            self.handle_interrupt(Interrupt::VBlank, registers, bus);
-            return;
+    /// are enabled. This is called after every instruction and returns the cycles taken
+    /// by the dispatch, which is 0 if nothing was dispatched
+    pub(crate) fn execute_interrupts<B: CpuBus>(
+        &mut self,
+        registers: &mut Registers,
+        bus: &mut B,
+    ) -> Cycles {
+        // IME is checked before the `EI` of the previous instruction takes effect, so the
+        // instruction after `EI` always runs before an interrupt is handled
+        let ime = self.ime;
+        if self.ime_scheduled {
+            self.ime = true;
+            self.ime_scheduled = false;
         }
 
-        if interrupt_enable.get_bit(1) && interrupt_flag.get_bit(1) {
-            self.handle_interrupt(Interrupt::Stat, registers, bus);
-            return;
-        }
+        let pending_interrupts = get_pending_interrupts(bus.bus_mut());
 
-        if interrupt_enable.get_bit(2) && interrupt_flag.get_bit(2) {
-            self.handle_interrupt(Interrupt::Timer, registers, bus);
+        // A pending interrupt wakes the CPU up even if IME is off, in that case it just
+        // continues after the `HALT` instruction
+        if pending_interrupts != 0 {
+            self.halt = false;
         }
-    }
 
-    /// We only dispatch an interrupt if IME is true, but regardless of that we reset the
-    /// interrupt bit in IF, this is not used by the emulator but by the program itself
-    fn handle_interrupt(&mut self, interrupt: Interrupt, registers: &mut Registers, bus: &mut Bus) {
-        let mut input_flags = bus.read(IF);
-        input_flags.set_bit(interrupt as u8, false);
-        bus.write(IF, input_flags);
+        // With IME off the interrupts stay pending in IF until the game handles them
+        if !ime || pending_interrupts == 0 {
+            return 0;
+        }
 
-        if self.ime {
-            self.dispatch_interrupt(interrupt, registers, bus);
+        self.dispatch_interrupt(registers, bus);
 
-            // We disable IME so interrupts are not called immediately, interrupts
-            // typically end with a `RETI` instruction that turns this back on
-            self.ime = false;
+        // We disable IME so interrupts are not called immediately, interrupts typically
+        // end with a `RETI` instruction that turns this back on
+        self.ime = false;
 
-            // And we also un-halt the CPU
-            self.halt = false;
-        }
+        5
     }
 
-    /// We `CALL` the arbitrary address specified by the interrupt
-    fn dispatch_interrupt(&self, interrupt: Interrupt, registers: &mut Registers, bus: &mut Bus) {
-        let return_address = match interrupt {
-            Interrupt::VBlank => 0x40,
-            Interrupt::Stat => 0x48,
-            Interrupt::Timer => 0x50,
-        };
+    /// We `CALL` the arbitrary address specified by the interrupt, this takes 5 cycles: 2
+    /// where nothing happens, 2 to push PC and 1 to set it.
+    ///
+    /// The interrupt is only chosen after the high byte of PC is pushed, if that push
+    /// overwrites IE (SP was 0x0000) the interrupt can be cancelled. In that case nothing
+    /// is acknowledged in IF and the CPU jumps to 0x0000 instead
+    fn dispatch_interrupt<B: CpuBus>(&self, registers: &mut Registers, bus: &mut B) {
+        bus.tick();
+        bus.tick();
 
         // This is like the call instruction but we don't subtract three
         let (p, c) = split_u16_into_two_u8s(registers.pc);
         registers.sp = registers.sp.wrapping_sub(1);
         bus.write(registers.sp, p);
+
+        let interrupt = Interrupt::highest_priority(get_pending_interrupts(bus.bus_mut()));
+
         registers.sp = registers.sp.wrapping_sub(1);
         bus.write(registers.sp, c);
-        registers.pc = return_address;
+
+        registers.pc = match interrupt {
+            Some(interrupt) => {
+                // The interrupt bit in IF is reset so the interrupt is not dispatched again
+                let bus = bus.bus_mut();
+                let mut input_flags = bus.read(IF);
+                input_flags.set_bit(interrupt as u8, false);
+                bus.write(IF, input_flags);
+
+                interrupt.address()
+            }
+            None => 0x0000,
+        };
+
+        bus.tick();
     }
 }
+
+/// The interrupts that are both requested and enabled, only the lower 5 bits are used
+pub(crate) fn get_pending_interrupts(bus: &Bus) -> u8 {
+    bus.ie & bus.read(IF) & 0b00011111
+}
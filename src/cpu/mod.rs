@@ -1,9 +1,9 @@
 use crate::{
-    bus::Bus,
-    common::Bit,
-    consts::cpu::{DIV, IF, TAC, TIMA, TMA},
+    registers::Registers,
+    save_state::{SaveState, SaveStateError, StateReader, StateWriter},
 };
 
+pub(crate) mod cpu_bus;
 mod interrupts;
 mod opcodes;
 mod opcodes_cb;
@@ -20,82 +20,60 @@ pub struct Cpu {
     /// interrupts should be handled or not
     pub ime: bool,
 
+    /// `EI` only turns IME on after the instruction that follows it, so `EI` followed by
+    /// `RET` returns before any interrupt gets handled
+    pub(crate) ime_scheduled: bool,
+
     /// Wheter or not the CPU is halted
     pub halt: bool,
 
-    /// A cycle counter for keeping track of when to increment the DIV register
-    pub(crate) div_cycle_counter: u8,
+    /// `HALT` with IME off and an interrupt already pending does not halt, instead the
+    /// CPU fails to increment PC after reading the next opcode, so that byte is read twice
+    pub(crate) halt_bug: bool,
 
-    /// A cycle counter for keeping track of when to increment the TIMA register
-    pub(crate) tima_cycle_counter: u16,
+    /// Wheter or not the GameBoy is in the low power mode entered by `STOP`, everything
+    /// is stopped until a button is pressed
+    pub stopped: bool,
 }
 
 impl Cpu {
     pub(crate) fn new() -> Self {
         Self {
             ime: false,
+            ime_scheduled: false,
             halt: false,
-            div_cycle_counter: 1,
-            tima_cycle_counter: 0,
+            halt_bug: false,
+            stopped: false,
         }
     }
 
-    /// Increment the div register every 64 cycles
-    pub(crate) fn update_div_register(&mut self, bus: &mut Bus, cycles_num: u8) {
-        // When we write to the div register we also reset the cycle counter
-        if bus.needs_to_reset_div_register {
-            // I don't know why this must be reset to 1, but it works!
-            self.div_cycle_counter = 1;
-            bus.needs_to_reset_div_register = false;
-        }
-
-        self.div_cycle_counter += cycles_num;
-
-        if self.div_cycle_counter >= 64 {
-            self.div_cycle_counter -= 64;
-
-            // Increment the DIV register, by using IO directly, otherwise writing to DIV
-            // will trigger a DIV reset
-            bus.io[0x04] = bus.read(DIV).wrapping_add(1);
+    /// Called after the opcode is read, if the HALT bug happened PC should not have been
+    /// incremented, so the rest of the instruction reads the opcode byte again. Moving PC
+    /// back by one does exactly that
+    pub(crate) fn apply_halt_bug(&mut self, registers: &mut Registers) {
+        if self.halt_bug {
+            registers.pc = registers.pc.wrapping_sub(1);
+            self.halt_bug = false;
         }
     }
+}
 
-    /// Increse the tima register based on the contents of the timer control register
-    pub(crate) fn update_tima_register(&mut self, bus: &mut Bus, cycles_num: u8) {
-        let timer_counter = bus.read(TIMA);
-        let timer_control = bus.read(TAC);
-        let timer_module = bus.read(TMA);
-
-        // The second byte of timer control indicates wheter or not we are counting
-        if !timer_control.get_bit(2) {
-            return;
-        }
-
-        self.tima_cycle_counter += cycles_num as u16;
-
-        // This is the threshold at which we increment the timer counter
-        let increment_every = match (timer_control.get_bit(1), timer_control.get_bit(0)) {
-            (false, false) => 256,
-            (false, true) => 4,
-            (true, false) => 16,
-            (true, true) => 64,
-        };
-
-        if self.tima_cycle_counter > increment_every {
-            self.tima_cycle_counter -= increment_every;
+impl SaveState for Cpu {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ime);
+        writer.write_bool(self.ime_scheduled);
+        writer.write_bool(self.halt);
+        writer.write_bool(self.halt_bug);
+        writer.write_bool(self.stopped);
+    }
 
-            // When the timer counter overflows we have to run a timer interrupt
-            let (result, has_overflown) = timer_counter.overflowing_add(1);
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.ime = reader.read_bool()?;
+        self.ime_scheduled = reader.read_bool()?;
+        self.halt = reader.read_bool()?;
+        self.halt_bug = reader.read_bool()?;
+        self.stopped = reader.read_bool()?;
 
-            if has_overflown {
-                // When overflowing, we both trigger a timer interrupt and we reset the
-                // value of the timer counter to that of the timer module
-                bus.write(IF, bus.read(IF) | 0b00000100); // Enabling timer interrupt
-                bus.write(TIMA, timer_module);
-            } else {
-                // When not overflowing, we just increment the timer
-                bus.write(TAC, result);
-            }
-        }
+        Ok(())
     }
 }
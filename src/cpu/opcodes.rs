@@ -1,11 +1,11 @@
 use crate::{
-    bus::Bus,
     common::{merge_two_u8s_into_u16, split_u16_into_two_u8s},
+    consts::cpu::DIV,
     flags::{FlagOperations, Flags},
     registers::Registers,
 };
 
-use super::{Bytes, Cpu, Cycles};
+use super::{cpu_bus::CpuBus, interrupts::get_pending_interrupts, Bytes, Cpu, Cycles};
 
 pub(crate) const CALL: u8 = 0xCD;
 pub(crate) const JUMP: u8 = 0xC3;
@@ -13,12 +13,12 @@ pub(crate) const RELATIVE_JUMP: u8 = 0x18;
 pub(crate) const RET: u8 = 0xC9;
 
 impl Cpu {
-    pub fn interpret_opcode(
+    pub fn interpret_opcode<B: CpuBus>(
         &mut self,
         opcode: u8,
         flags: &mut Flags,
         regs: &mut Registers,
-        bus: &mut Bus,
+        bus: &mut B,
     ) -> (Bytes, Cycles) {
         if self.halt {
             return (0, 1);
@@ -167,6 +167,17 @@ impl Cpu {
                 (1, 1)
             }
 
+            // Instruction `STOP` - 00010000
+            // Enters the low power mode, the byte after it is skipped. Entering this mode
+            // also resets DIV. On the GameBoy Color it switches the CPU speed instead, if
+            // the game prepared the switch in KEY1
+            0x10 => {
+                self.stopped = !bus.bus_mut().switch_speed();
+                bus.bus_mut().write(DIV, 0);
+
+                (2, 1)
+            }
+
             // Instruction `RLA` - 00010111
             // Exactly like the CB instruction `RL a` which is the same opcode but resets
             // zero flag
@@ -273,9 +284,16 @@ impl Cpu {
             }
 
             // Instruction Halt
-            // This is in the middle of the ld instructions, TODO: implement this fully
+            // This is in the middle of the ld instructions. The CPU stops until an
+            // interrupt is pending, if one is already pending it doesn't stop at all
             0x76 => {
-                self.halt = true;
+                let is_interrupt_pending = get_pending_interrupts(bus.bus_mut()) != 0;
+
+                match (is_interrupt_pending, self.ime) {
+                    (false, _) => self.halt = true,
+                    (true, true) => {}
+                    (true, false) => self.halt_bug = true,
+                }
 
                 (1, 1)
             }
@@ -321,6 +339,9 @@ impl Cpu {
             // Instruction `RET condition` - 110cc000
             // Like instruction ret but only if condition applies
             0xC0 | 0xC8 | 0xD0 | 0xD8 => {
+                // Checking the condition takes a cycle
+                bus.tick();
+
                 if flags.is_condition_valid(opcode >> 3) {
                     self.interpret_opcode(RET, flags, regs, bus);
                     (0, 5)
@@ -347,7 +368,7 @@ impl Cpu {
             0xC2 | 0xCA | 0xD2 | 0xDA => {
                 if flags.is_condition_valid(opcode >> 3) {
                     self.interpret_opcode(JUMP, flags, regs, bus);
-                    (0, 6)
+                    (0, 4)
                 } else {
                     (3, 3)
                 }
@@ -367,7 +388,7 @@ impl Cpu {
             0xC4 | 0xCC | 0xD4 | 0xDC => {
                 if flags.is_condition_valid(opcode >> 3) {
                     self.interpret_opcode(CALL, flags, regs, bus);
-                    (0, 4)
+                    (0, 6)
                 } else {
                     (3, 3)
                 }
@@ -381,6 +402,9 @@ impl Cpu {
                 let (high, low) =
                     split_u16_into_two_u8s(regs.get_register_couple_with_flags(opcode >> 4, flags));
 
+                // Decrementing SP takes a cycle before the writes
+                bus.tick();
+
                 regs.sp = regs.sp.wrapping_sub(1);
                 bus.write(regs.sp, high);
                 regs.sp = regs.sp.wrapping_sub(1);
@@ -434,6 +458,7 @@ impl Cpu {
             0xCD => {
                 let (p, c) = split_u16_into_two_u8s(regs.pc.wrapping_add(3));
                 let immediate_data = bus.next_two(regs);
+                bus.tick();
 
                 regs.sp = regs.sp.wrapping_sub(1);
                 bus.write(regs.sp, p);
@@ -451,6 +476,8 @@ impl Cpu {
                 // Pushing, NOTE: we add one because we push the pc of the next
                 // instruction
                 let (p, c) = split_u16_into_two_u8s(regs.pc.wrapping_add(1));
+                bus.tick();
+
                 regs.sp = regs.sp.wrapping_sub(1);
                 bus.write(regs.sp, p);
                 regs.sp = regs.sp.wrapping_sub(1);
@@ -548,9 +575,11 @@ impl Cpu {
                 (1, 2)
             }
 
-            // Disable Interrupt Master Enable flag
+            // Disable Interrupt Master Enable flag, this also cancels a previous `EI` that
+            // didn't take effect yet
             0xF3 => {
                 self.ime = false;
+                self.ime_scheduled = false;
                 (1, 1)
             }
 
@@ -593,9 +622,10 @@ impl Cpu {
                 (3, 4)
             }
 
-            // Enable Interrupt Master Enable flag
+            // Enable Interrupt Master Enable flag, this is delayed by one instruction, see
+            // `execute_interrupts`
             0xFB => {
-                self.ime = true;
+                self.ime_scheduled = true;
                 (1, 1)
             }
 
@@ -1,14 +1,14 @@
-use crate::{bus::Bus, common::Bit, flags::Flags, registers::Registers};
+use crate::{common::Bit, flags::Flags, registers::Registers};
 
-use super::{Bytes, Cpu, Cycles};
+use super::{cpu_bus::CpuBus, Bytes, Cpu, Cycles};
 
 impl Cpu {
-    pub fn interpret_cb_opcode(
+    pub fn interpret_cb_opcode<B: CpuBus>(
         &mut self,
         opcode: u8,
         flags: &mut Flags,
         regs: &mut Registers,
-        bus: &mut Bus,
+        bus: &mut B,
     ) -> (Bytes, Cycles) {
         match opcode {
             // Instruction `RLC r` - 00000rrr
@@ -1,4 +1,7 @@
-use crate::common::Bit;
+use crate::{
+    common::Bit,
+    save_state::{SaveState, SaveStateError, StateReader, StateWriter},
+};
 
 #[derive(PartialEq, Eq)]
 pub struct Flags {
@@ -25,6 +28,24 @@ impl Flags {
         }
     }
 
+    pub(crate) fn new_cgb() -> Self {
+        Self {
+            zero: true,
+            subtraction: false,
+            half_carry: false,
+            carry: false,
+        }
+    }
+
+    pub(crate) fn new_before_boot() -> Self {
+        Self {
+            zero: false,
+            subtraction: false,
+            half_carry: false,
+            carry: false,
+        }
+    }
+
     pub(crate) fn is_condition_valid(&self, condition_num: u8) -> bool {
         match condition_num & 0b00000011 {
             0 => !self.zero,
@@ -54,6 +75,17 @@ impl Flags {
     }
 }
 
+impl SaveState for Flags {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.get_byte());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.set_from_byte(reader.read_u8()?);
+        Ok(())
+    }
+}
+
 /// Operations that work like the wrapping ones but also give you the flags
 pub(crate) trait FlagOperations {
     fn flag_add(&self, rhs: Self) -> (Self, Flags)
@@ -1,94 +1,39 @@
 use crate::{
     bus::Bus,
-    common::Bit,
-    consts::{
-        cpu::IF,
-        gpu::{LYC, STAT},
-    },
+    consts::{cpu::IF, display::DISPLAY_SIZE_Y},
 };
 
-use super::{Gpu, GpuState, Layers};
+use super::{Gpu, Layers};
 
 impl Gpu {
+    /// The GPU rests until the end of the line, the next line is started by `next_line`
     pub(super) fn hblank(&mut self, layers: &mut Layers, bus: &mut Bus) {
         if self.ticks == 0 {
             self.x = 0;
-            self.y += 1;
             self.dump_slice = true;
             self.fifo.clear();
 
             layers
                 .iter_mut()
                 .for_each(|layer| layer.at_hblank(bus, self));
-        }
-
-        // Setting interrupts
-        if self.ticks == 0 {
-            let mut interrupt_flag = bus.read(IF);
-            let stat = bus.read(STAT);
-
-            // Stat interrupt. Stat.3 indicates HBlank
-            if stat.get_bit(3) {
-                interrupt_flag |= 0b00000010;
-            }
 
-            // Stat interrupt. We need to check for LYC == LY
-            if stat.get_bit(6) && self.y == bus.read(LYC) {
-                interrupt_flag |= 0b00000010;
-            }
-
-            bus.write(IF, interrupt_flag);
+            bus.on_hblank_vram_dma();
         }
 
-        self.switch_when_ticks(
-            // This should be 376 - the duration of the pixel transfer, but pixel transfer is
-            // hard-coded to 172 as of now
-            376 - 172,
-            if self.y == 144 {
-                GpuState::VBlank
-            } else {
-                GpuState::PixelTransfer
-            },
-        );
+        self.ticks += 1;
     }
 
+    /// VBlank lasts 10 lines, `next_line` counts them
     pub(super) fn vblank(&mut self, layers: &mut Layers, bus: &mut Bus) {
-        /// Amount of ticks needed to render a vblank line
-        const VBLANK_LINE_TICKS: u16 = 456;
-
-        if self.ticks == 0 {
+        if self.ticks == 0 && self.y == DISPLAY_SIZE_Y as u8 {
             layers
                 .iter_mut()
                 .for_each(|layer| layer.at_vblank(bus, self));
-        }
-
-        // After every line
-        if self.ticks % VBLANK_LINE_TICKS == 0 {
-            self.y += 1;
-        }
-
-        // Setting interrupts
-        if self.ticks == 0 {
-            let mut interrupt_flag = bus.read(IF);
-            let stat = bus.read(STAT);
-
-            // VBlank interrupt
-            interrupt_flag |= 0b00000001;
-
-            // Stat interrupt. Stat.3 indicates VBlank
-            if stat.get_bit(4) {
-                interrupt_flag |= 0b00000010;
-            }
-
-            // Stat interrupt. We need to check for LYC == LY
-            if stat.get_bit(6) && self.y == bus.read(LYC) {
-                interrupt_flag |= 0b00000010;
-            }
 
-            bus.write(IF, interrupt_flag);
+            bus.on_vblank_sgb(&self.screen);
+            bus.write(IF, bus.read(IF) | 0b00000001); // Enabling VBlank interrupt
         }
 
-        // There are 10 lines of vblank
-        self.switch_when_ticks(VBLANK_LINE_TICKS * 10, GpuState::OamSearch);
+        self.ticks += 1;
     }
 }
@@ -1,30 +1,68 @@
 mod blanks;
 mod oam_search;
 pub(crate) mod pixel_transfer;
+mod stat;
 
 use pixel_transfer::{sprite::SpriteData, Layers};
 
 use crate::{
     bus::Bus,
+    common::Bit,
     consts::{
+        bus::IO_START,
         display::{DISPLAY_SIZE_X, DISPLAY_SIZE_Y},
-        gpu::LY,
+        gpu::{LCDC, LY, STAT},
     },
+    save_state::{SaveState, SaveStateError, StateReader, StateWriter},
 };
 
 use self::pixel_transfer::PixelTransferState;
 
+/// The amount of dots every line takes
+const LINE_TICKS: u16 = 456;
+
+/// The amount of lines, including the 10 lines of VBlank
+const LINES: u8 = 154;
+
+/// The amount of dots every frame takes, even when the LCD is off
+const FRAME_TICKS: u32 = LINE_TICKS as u32 * LINES as u32;
+
+/// The first line after turning the LCD on is shorter by this many dots
+const FIRST_LINE_SKIPPED_TICKS: u16 = 4;
+
+/// What the GameBoy Color shows when the LCD is off
+const CGB_WHITE: u16 = 0x7FFF;
+
 impl Gpu {
+    /// Counts the current dot, and switches to `new_state` once the state has lasted
+    /// `ticks` dots, so that the next dot is in the new state
     fn switch_when_ticks(&mut self, ticks: u16, new_state: GpuState) {
+        self.ticks += 1;
+
         if self.ticks >= ticks {
             self.state = new_state;
             self.ticks = 0;
-        } else {
-            self.ticks += 1;
         }
     }
 
     pub(crate) fn tick(&mut self, layers: &mut Layers, bus: &mut Bus) {
+        match (bus.read(LCDC).get_bit(7), self.is_lcd_on) {
+            (false, true) => self.turn_lcd_off(bus),
+            (true, false) => self.turn_lcd_on(layers, bus),
+            _ => {}
+        }
+
+        if !self.is_lcd_on {
+            // Frames keep going even if nothing is drawn, so the frontend keeps running
+            self.lcd_off_ticks += 1;
+            if self.lcd_off_ticks == FRAME_TICKS {
+                self.lcd_off_ticks = 0;
+                self.frame_count = self.frame_count.wrapping_add(1);
+            }
+
+            return;
+        }
+
         match self.state {
             GpuState::OamSearch => self.oam_search(bus),
             GpuState::PixelTransfer => self.pixel_transfer(layers, bus),
@@ -32,17 +70,131 @@ impl Gpu {
             GpuState::VBlank => self.vblank(layers, bus),
         }
 
-        bus.write(LY, self.y);
+        // Every line takes the same time, however long the pixel transfer was
+        self.line_ticks += 1;
+        if self.line_ticks == LINE_TICKS {
+            self.line_ticks = 0;
+            self.next_line();
+        }
+
+        bus.write(LY, self.get_ly());
+        self.update_stat(bus);
+        bus.gpu_state = self.get_visible_state();
+    }
+
+    /// The state as seen by the rest of the GameBoy, this is only different on the first
+    /// line after turning the LCD on, which says it's in HBlank instead of OAM Search
+    fn get_visible_state(&self) -> GpuState {
+        match (self.state, self.is_first_line_after_lcd_on) {
+            (GpuState::OamSearch, true) => GpuState::HBlank,
+            (state, _) => state,
+        }
+    }
+
+    /// Called when a line ends, the lines after the screen are VBlank and then it starts
+    /// from the top again
+    fn next_line(&mut self) {
+        self.y += 1;
+        self.ticks = 0;
+        self.is_first_line_after_lcd_on = false;
+
+        self.state = match self.state {
+            GpuState::VBlank if self.y == LINES => {
+                self.y = 0;
+                self.frame_count = self.frame_count.wrapping_add(1);
+                GpuState::OamSearch
+            }
+            GpuState::VBlank => GpuState::VBlank,
+            _ if self.y == DISPLAY_SIZE_Y as u8 => GpuState::VBlank,
+            _ => GpuState::OamSearch,
+        };
+    }
+
+    /// With the LCD off the GPU does nothing, LY stays at 0 and STAT says it's in HBlank,
+    /// so the game can use VRAM and OAM whenever it wants
+    fn turn_lcd_off(&mut self, bus: &mut Bus) {
+        self.is_lcd_on = false;
+        self.lcd_off_ticks = 0;
+        self.screen = [[Color::Light; DISPLAY_SIZE_X]; DISPLAY_SIZE_Y];
+        self.screen_palettes = [[PaletteRegister::Bgp; DISPLAY_SIZE_X]; DISPLAY_SIZE_Y];
+        self.cgb_screen = [[CGB_WHITE; DISPLAY_SIZE_X]; DISPLAY_SIZE_Y];
+
+        self.state = GpuState::HBlank;
+        self.y = 0;
+        self.line_ticks = 0;
+        self.stat_line = false;
+
+        bus.write(LY, 0);
+        bus.io[(STAT - IO_START as u16) as usize] &= 0b11111100;
+        bus.gpu_state = self.state;
+    }
+
+    /// The GPU starts again from the first line. That line is a bit shorter than the
+    /// others, and it doesn't look for sprites, STAT says it's in HBlank instead. Since
+    /// `ticks` doesn't start from 0, `oam_search` doesn't fill `sprites`
+    fn turn_lcd_on(&mut self, layers: &mut Layers, bus: &mut Bus) {
+        self.is_lcd_on = true;
+        self.is_first_line_after_lcd_on = true;
+
+        self.state = GpuState::OamSearch;
+        self.ticks = FIRST_LINE_SKIPPED_TICKS;
+        self.x = 0;
+        self.y = 0;
+        self.line_ticks = FIRST_LINE_SKIPPED_TICKS;
+        self.stalled_ticks = 0;
+        self.number_of_slices_pushed = 0;
+        self.sprites.clear();
+
+        layers
+            .iter_mut()
+            .for_each(|layer| layer.at_vblank(bus, self));
+    }
+
+    /// LY is the line being drawn, except on the last line of VBlank, where it goes back
+    /// to 0 after the first cycle
+    fn get_ly(&self) -> u8 {
+        match self.y == LINES - 1 && self.line_ticks >= 4 {
+            true => 0,
+            false => self.y,
+        }
     }
 }
 
 pub struct Gpu {
     pub screen: [[Color; DISPLAY_SIZE_X]; DISPLAY_SIZE_Y],
+
+    /// The palette register every pixel of `screen` was colored with, so that the
+    /// background and the two sprite palettes can have different colors, see `Palette`
+    pub screen_palettes: [[PaletteRegister; DISPLAY_SIZE_X]; DISPLAY_SIZE_Y],
+
+    /// In GameBoy Color mode the screen is drawn here instead, every color is in the
+    /// RGB555 format, with red in the lowest bits
+    pub cgb_screen: [[u16; DISPLAY_SIZE_X]; DISPLAY_SIZE_Y],
+
     pub ticks: u16,
     pub state: GpuState,
     pub x: u8,
     pub y: u8,
 
+    /// The dots since the start of the current line
+    line_ticks: u16,
+
+    /// The STAT interrupt is requested when this goes from false to true, see `stat.rs`
+    stat_line: bool,
+
+    /// LCDC.7, the GPU is reset when it's turned off
+    is_lcd_on: bool,
+
+    /// The first line after turning the LCD on doesn't look for sprites
+    is_first_line_after_lcd_on: bool,
+
+    /// The dots since the LCD was turned off, or since the last frame that went by while
+    /// it was off
+    lcd_off_ticks: u32,
+
+    /// Incremented every time a frame ends, used by `GameBoy::step_for_a_frame`
+    pub(crate) frame_count: u32,
+
     fifo: Vec<PixelData>,
 
     /// This is filled during OAM Search
@@ -66,16 +218,27 @@ pub struct Gpu {
 
     /// `number_of_slices_pushed` * 8
     virtual_x: u8,
+
+    /// The dots left before the fetcher starts again, see `Layer::get_fetch_penalty`
+    stalled_ticks: u16,
 }
 
 impl Gpu {
     pub(crate) fn new() -> Self {
         Self {
             screen: [[Color::Light; DISPLAY_SIZE_X]; DISPLAY_SIZE_Y],
+            screen_palettes: [[PaletteRegister::Bgp; DISPLAY_SIZE_X]; DISPLAY_SIZE_Y],
+            cgb_screen: [[CGB_WHITE; DISPLAY_SIZE_X]; DISPLAY_SIZE_Y],
             ticks: 0,
             state: GpuState::OamSearch,
             x: 0,
             y: 0,
+            line_ticks: 0,
+            stat_line: false,
+            is_lcd_on: true,
+            is_first_line_after_lcd_on: false,
+            lcd_off_ticks: 0,
+            frame_count: 0,
             fifo: Vec::new(),
             sprites: Vec::new(),
             pixel_transfer_state: PixelTransferState::GetTile,
@@ -83,6 +246,7 @@ impl Gpu {
             dump_slice: true,
             number_of_slices_pushed: 0,
             virtual_x: 0,
+            stalled_ticks: 0,
         }
     }
 }
@@ -95,7 +259,14 @@ pub enum Color {
     Dark = 3,
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaletteRegister {
+    Bgp = 0,
+    Obp0 = 1,
+    Obp1 = 2,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum GpuState {
     OamSearch,
     PixelTransfer,
@@ -111,6 +282,14 @@ pub(crate) struct PixelData {
     /// This dictates the order of pixel drawing, check the code for sprite mixing in
     /// `pixel_transfer/mod.rs` for more info
     pub(crate) z_index: u8,
+
+    /// The CGB palette the color is taken from, `is_sprite` tells whether it's one of the
+    /// background palettes or one of the object palettes
+    pub(crate) palette: u8,
+    pub(crate) is_sprite: bool,
+
+    /// On the GameBoy Color background tiles can be drawn above sprites
+    pub(crate) has_priority: bool,
 }
 
 #[derive(Clone, Copy)]
@@ -123,3 +302,167 @@ pub(crate) enum Priority {
     /// When the above slice's pixels are drawn only above light pixels
     AboveLight,
 }
+
+impl SaveState for Gpu {
+    fn save_state(&self, writer: &mut StateWriter) {
+        for line in &self.screen {
+            for color in line {
+                writer.write_u8(*color as u8);
+            }
+        }
+
+        for line in &self.screen_palettes {
+            for register in line {
+                writer.write_u8(*register as u8);
+            }
+        }
+
+        for line in &self.cgb_screen {
+            for color in line {
+                writer.write_u16(*color);
+            }
+        }
+
+        writer.write_u16(self.ticks);
+        writer.write_u8(self.state as u8);
+        writer.write_u8(self.x);
+        writer.write_u8(self.y);
+        writer.write_u16(self.line_ticks);
+        writer.write_bool(self.stat_line);
+        writer.write_bool(self.is_lcd_on);
+        writer.write_bool(self.is_first_line_after_lcd_on);
+        writer.write_u32(self.lcd_off_ticks);
+
+        writer.write_u32(self.fifo.len() as u32);
+        for pixel_data in &self.fifo {
+            pixel_data.save_state(writer);
+        }
+
+        writer.write_u32(self.sprites.len() as u32);
+        for sprite in &self.sprites {
+            sprite.save_state(writer);
+        }
+
+        writer.write_u8(self.pixel_transfer_state as u8);
+        writer.write_bool(self.is_pixel_transfer_first_call);
+        writer.write_bool(self.dump_slice);
+        writer.write_u8(self.number_of_slices_pushed);
+        writer.write_u8(self.virtual_x);
+        writer.write_u16(self.stalled_ticks);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        for line in self.screen.iter_mut() {
+            for color in line.iter_mut() {
+                *color = Color::from_state(reader.read_u8()?)?;
+            }
+        }
+
+        for line in self.screen_palettes.iter_mut() {
+            for register in line.iter_mut() {
+                *register = PaletteRegister::from_state(reader.read_u8()?)?;
+            }
+        }
+
+        for line in self.cgb_screen.iter_mut() {
+            for color in line.iter_mut() {
+                *color = reader.read_u16()?;
+            }
+        }
+
+        self.ticks = reader.read_u16()?;
+        self.state = GpuState::from_state(reader.read_u8()?)?;
+        self.x = reader.read_u8()?;
+        self.y = reader.read_u8()?;
+        self.line_ticks = reader.read_u16()?;
+        self.stat_line = reader.read_bool()?;
+        self.is_lcd_on = reader.read_bool()?;
+        self.is_first_line_after_lcd_on = reader.read_bool()?;
+        self.lcd_off_ticks = reader.read_u32()?;
+
+        self.fifo.clear();
+        for _ in 0..reader.read_u32()? {
+            self.fifo.push(PixelData::load_state(reader)?);
+        }
+
+        self.sprites.clear();
+        for _ in 0..reader.read_u32()? {
+            self.sprites.push(SpriteData::load_state(reader)?);
+        }
+
+        self.pixel_transfer_state = PixelTransferState::from_state(reader.read_u8()?)?;
+        self.is_pixel_transfer_first_call = reader.read_bool()?;
+        self.dump_slice = reader.read_bool()?;
+        self.number_of_slices_pushed = reader.read_u8()?;
+        self.virtual_x = reader.read_u8()?;
+        self.stalled_ticks = reader.read_u16()?;
+
+        Ok(())
+    }
+}
+
+impl Color {
+    pub(crate) fn from_state(value: u8) -> Result<Self, SaveStateError> {
+        match value {
+            0 => Ok(Color::Light),
+            1 => Ok(Color::MediumlyLight),
+            2 => Ok(Color::MediumlyDark),
+            3 => Ok(Color::Dark),
+            _ => Err(SaveStateError::Corrupted),
+        }
+    }
+}
+
+impl PaletteRegister {
+    pub(crate) fn from_state(value: u8) -> Result<Self, SaveStateError> {
+        match value {
+            0 => Ok(PaletteRegister::Bgp),
+            1 => Ok(PaletteRegister::Obp0),
+            2 => Ok(PaletteRegister::Obp1),
+            _ => Err(SaveStateError::Corrupted),
+        }
+    }
+}
+
+impl GpuState {
+    pub(crate) fn from_state(value: u8) -> Result<Self, SaveStateError> {
+        match value {
+            0 => Ok(GpuState::OamSearch),
+            1 => Ok(GpuState::PixelTransfer),
+            2 => Ok(GpuState::HBlank),
+            3 => Ok(GpuState::VBlank),
+            _ => Err(SaveStateError::Corrupted),
+        }
+    }
+}
+
+impl PixelData {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.color as u8);
+        writer.write_u8(self.z_index);
+        writer.write_u8(self.palette);
+        writer.write_bool(self.is_sprite);
+        writer.write_bool(self.has_priority);
+    }
+
+    fn load_state(reader: &mut StateReader) -> Result<Self, SaveStateError> {
+        Ok(Self {
+            color: Color::from_state(reader.read_u8()?)?,
+            z_index: reader.read_u8()?,
+            palette: reader.read_u8()?,
+            is_sprite: reader.read_bool()?,
+            has_priority: reader.read_bool()?,
+        })
+    }
+}
+
+impl Priority {
+    pub(crate) fn from_state(value: u8) -> Result<Self, SaveStateError> {
+        match value {
+            0 => Ok(Priority::AlwaysAbove),
+            1 => Ok(Priority::TransparentLight),
+            2 => Ok(Priority::AboveLight),
+            _ => Err(SaveStateError::Corrupted),
+        }
+    }
+}
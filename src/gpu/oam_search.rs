@@ -1,8 +1,4 @@
-use crate::{
-    bus::Bus,
-    common::Bit,
-    consts::{cpu::IF, gpu::STAT},
-};
+use crate::{bus::Bus, common::Bit};
 
 use super::{
     pixel_transfer::sprite::{Palette, SpriteData},
@@ -11,19 +7,7 @@ use super::{
 
 impl Gpu {
     pub(super) fn oam_search(&mut self, bus: &mut Bus) {
-        // Setting interrupts
         if self.ticks == 0 {
-            let interrupt_flag = bus.read(IF);
-            let stat = bus.read(STAT);
-
-            // Stat interrupt. Stat.3 indicates OAM Search
-            if stat.get_bit(5) {
-                bus.write(IF, interrupt_flag | 0b00000010);
-            }
-        }
-
-        if self.ticks == 0 {
-            self.y = 0;
             self.sprites.clear();
 
             // We access sprites in reverse because the sprite with the lowest address has
@@ -39,10 +23,10 @@ impl Gpu {
 
 impl Bus {
     fn get_sprite_data(&self, address: u16) -> SpriteData {
-        let y = self.read(address - 3);
-        let x = self.read(address - 2);
-        let tile_number = self.read(address - 1);
-        let flags = self.read(address);
+        let y = self.ppu_read_oam(address - 3);
+        let x = self.ppu_read_oam(address - 2);
+        let tile_number = self.ppu_read_oam(address - 1);
+        let flags = self.ppu_read_oam(address);
 
         SpriteData {
             y,
@@ -58,6 +42,11 @@ impl Bus {
             },
             x_flip: flags.get_bit(5),
             y_flip: flags.get_bit(6),
+            cgb_palette: flags & 0b111,
+            bank: match self.is_cgb {
+                true => flags.get_bit(3) as u8,
+                false => 0,
+            },
         }
     }
 }
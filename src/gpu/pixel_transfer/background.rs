@@ -3,6 +3,7 @@ use crate::{
     common::Bit,
     consts::gpu::{BGP, LCDC, LY, SCX, SCY},
     gpu::{pixel_transfer::bytes_to_slice, Color, Gpu, PixelData, Priority},
+    save_state::{SaveState, SaveStateError, StateReader, StateWriter},
 };
 
 use super::{bools_to_color, vuza_gate, Layer, EMPTY_SLICE};
@@ -13,11 +14,16 @@ pub(crate) struct BackgroundLayer {
     tile_data_low: u16,
     tile_data_high: u16,
 
+    /// On the GameBoy Color every tile has attributes, they are stored in VRAM bank 1 at
+    /// the same address as the tile id
+    attributes: u8,
+
     // When scrolling the background layer with SCX, if SCX is not a multiple of 8, we
     // sometimes need to break multiple tiles in more parts and render them 8 pixels at a
     // time. These contain the data of the tiles we cut off
     leftover_low: u8,
     leftover_high: u8,
+    leftover_attributes: u8,
 }
 
 impl BackgroundLayer {
@@ -27,15 +33,19 @@ impl BackgroundLayer {
             tile_id: 0,
             tile_data_low: 0,
             tile_data_high: 0,
+            attributes: 0,
             leftover_low: 0,
             leftover_high: 0,
+            leftover_attributes: 0,
         }
     }
 }
 
 impl Layer for BackgroundLayer {
+    /// On the GameBoy Color LCDC.0 doesn't turn the background off, it takes away its
+    /// priority over the sprites instead
     fn is_layer_enabled(&self, bus: &Bus) -> bool {
-        if bus.read(LCDC).get_bit(0) {
+        if bus.is_cgb || bus.read(LCDC).get_bit(0) {
             return true;
         }
 
@@ -57,18 +67,32 @@ impl Layer for BackgroundLayer {
             | (bus.read(LY).wrapping_add(bus.read(SCY)) as u16 / 8) << 5
             | (gpu.virtual_x.wrapping_add(bus.read(SCX))) as u16 / 8;
 
-        self.tile_id = bus.read(address);
+        self.tile_id = bus.read_video_ram(0, address);
+        self.attributes = match bus.is_cgb {
+            true => bus.read_video_ram(1, address),
+            false => 0,
+        };
     }
 
     fn get_tile_data(&mut self, is_high_part: bool, _gpu: &Gpu, bus: &Bus) {
+        let mut row = bus.read(LY).wrapping_add(bus.read(SCY)) as u16 % 8;
+        if self.attributes.get_bit(6) {
+            row = 7 - row;
+        }
+
         // https://github.com/ISSOtm/pandocs/blob/rendering-internals/src/Rendering_Internals.md#get-tile-row-low
         let address = 0b100 << 13
             | vuza_gate(bus.read(LCDC), self.tile_id) << 12
             | (self.tile_id as u16) << 4
-            | (bus.read(LY).wrapping_add(bus.read(SCY)) as u16 % 8) << 1
+            | row << 1
             | is_high_part as u16;
 
-        let tile_data = bus.read(address);
+        let mut tile_data = bus.read_video_ram(self.attributes.get_bit(3) as u8, address);
+
+        // When horizontally flipping we have to reverse the read byte
+        if self.attributes.get_bit(5) {
+            tile_data = tile_data.reverse_bits();
+        }
 
         // We need to invert SCX otherwise it scrolls in the wrong direction
         let inverted_scx = 8 - (bus.read(SCX) % 8);
@@ -90,6 +114,7 @@ impl Layer for BackgroundLayer {
             // single tile otherwise the tiles won't look continous
             self.leftover_low = (self.tile_data_low >> 8) as u8;
             self.leftover_high = (self.tile_data_high >> 8) as u8;
+            self.leftover_attributes = self.attributes;
 
             return vec![];
         }
@@ -107,6 +132,24 @@ impl Layer for BackgroundLayer {
         self.leftover_low = (self.tile_data_low >> 8) as u8;
         self.leftover_high = (self.tile_data_high >> 8) as u8;
 
+        if bus.is_cgb {
+            // The pixels that come from the leftover bytes belong to the previous tile
+            let scx = bus.read(SCX) % 8;
+
+            for (i, pixel_data) in slice.iter_mut().enumerate() {
+                let attributes = match i as u8 >= scx {
+                    true => self.leftover_attributes,
+                    false => self.attributes,
+                };
+
+                pixel_data.palette = attributes & 0b111;
+                pixel_data.has_priority = attributes.get_bit(7) && bus.read(LCDC).get_bit(0);
+            }
+
+            self.leftover_attributes = self.attributes;
+            return slice;
+        }
+
         // Palette coloring (https://gbdev.io/pandocs/Palettes.html)
         let palette = bus.read(BGP);
         let id_0 = bools_to_color(palette.get_bit(1), palette.get_bit(0));
@@ -126,3 +169,29 @@ impl Layer for BackgroundLayer {
         slice
     }
 }
+
+impl SaveState for BackgroundLayer {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.lcdc_3);
+        writer.write_u8(self.tile_id);
+        writer.write_u16(self.tile_data_low);
+        writer.write_u16(self.tile_data_high);
+        writer.write_u8(self.attributes);
+        writer.write_u8(self.leftover_low);
+        writer.write_u8(self.leftover_high);
+        writer.write_u8(self.leftover_attributes);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.lcdc_3 = reader.read_bool()?;
+        self.tile_id = reader.read_u8()?;
+        self.tile_data_low = reader.read_u16()?;
+        self.tile_data_high = reader.read_u16()?;
+        self.attributes = reader.read_u8()?;
+        self.leftover_low = reader.read_u8()?;
+        self.leftover_high = reader.read_u8()?;
+        self.leftover_attributes = reader.read_u8()?;
+
+        Ok(())
+    }
+}
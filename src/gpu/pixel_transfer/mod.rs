@@ -5,8 +5,13 @@ pub(crate) mod background;
 pub(crate) mod sprite;
 pub(crate) mod window;
 
-use super::{Color, Gpu, GpuState, PixelData, Priority};
-use crate::{bus::Bus, common::Bit, consts::display::DISPLAY_SIZE_X};
+use super::{Color, Gpu, GpuState, PaletteRegister, PixelData, Priority};
+use crate::{
+    bus::Bus,
+    common::Bit,
+    consts::{display::DISPLAY_SIZE_X, gpu::SCX},
+    save_state::{SaveState, SaveStateError},
+};
 
 /// The GameBoy's GPU works by having three "layers", the background layer, the window
 /// layer and the sprite layer, this trait defines the parts that differ for every layer,
@@ -15,7 +20,7 @@ pub(crate) type Layers = [Box<dyn Layer>; 3];
 
 /// While being different, the layers all have the same interface
 #[allow(unused_variables)]
-pub(crate) trait Layer: Send {
+pub(crate) trait Layer: Send + SaveState {
     fn is_layer_enabled(&self, bus: &Bus) -> bool;
     fn mix_with_layer_below(&self) -> Priority;
     fn get_tile_step_1(&mut self, gpu: &Gpu, bus: &Bus);
@@ -23,12 +28,18 @@ pub(crate) trait Layer: Send {
     fn get_tile_data(&mut self, is_high_part: bool, gpu: &Gpu, bus: &Bus);
     fn push_pixels(&mut self, gpu: &Gpu, bus: &Bus) -> Vec<PixelData>;
 
+    /// The dots the fetcher stalls for after this layer fetched the current slice, this
+    /// is why the pixel transfer takes longer than 172 dots on most lines
+    fn get_fetch_penalty(&self, gpu: &Gpu, bus: &Bus) -> u16 {
+        0
+    }
+
     // Events
     fn at_hblank(&mut self, bus: &Bus, gpu: &Gpu) {}
     fn at_vblank(&mut self, bus: &Bus, gpu: &Gpu) {}
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub(super) enum PixelTransferState {
     GetTile,
     GetLowTileData,
@@ -37,6 +48,19 @@ pub(super) enum PixelTransferState {
     PushPixels,
 }
 
+impl PixelTransferState {
+    pub(super) fn from_state(value: u8) -> Result<Self, SaveStateError> {
+        match value {
+            0 => Ok(PixelTransferState::GetTile),
+            1 => Ok(PixelTransferState::GetLowTileData),
+            2 => Ok(PixelTransferState::GetHighTileData),
+            3 => Ok(PixelTransferState::Sleep),
+            4 => Ok(PixelTransferState::PushPixels),
+            _ => Err(SaveStateError::Corrupted),
+        }
+    }
+}
+
 impl Gpu {
     fn cycle_state(&mut self) {
         self.pixel_transfer_state = match self.pixel_transfer_state {
@@ -68,6 +92,20 @@ impl Gpu {
     /// This is a super helpful resource:
     /// https://github.com/ISSOtm/pandocs/blob/rendering-internals/src/Rendering_Internals.md
     pub(super) fn pixel_transfer(&mut self, layers: &mut Layers, bus: &mut Bus) {
+        // The first SCX % 8 pixels of the line are shifted out and thrown away, which
+        // takes a dot each
+        if self.ticks == 0 {
+            self.stalled_ticks = bus.read(SCX) as u16 % 8;
+        }
+
+        // While the fetcher is stalled no pixels are pushed either, the time lost here is
+        // taken away from HBlank
+        if self.stalled_ticks > 0 {
+            self.stalled_ticks -= 1;
+            self.ticks += 1;
+            return;
+        }
+
         match self.pixel_transfer_state {
             PixelTransferState::GetTile => self.get_tile(layers, bus),
             PixelTransferState::GetLowTileData => self.get_tile_data(false, layers, bus),
@@ -78,13 +116,29 @@ impl Gpu {
 
         // Pop one pixel and display it
         if let Some(pixel_data) = self.fifo.pop() {
-            self.screen[self.y as usize][self.x as usize] = pixel_data.color;
+            let (x, y) = (self.x as usize, self.y as usize);
+
+            match bus.is_cgb {
+                true => {
+                    self.cgb_screen[y][x] = bus.get_cgb_color(
+                        pixel_data.is_sprite,
+                        pixel_data.palette,
+                        pixel_data.color as u8,
+                    )
+                }
+                false => {
+                    self.screen[y][x] = pixel_data.color;
+                    self.screen_palettes[y][x] = match (pixel_data.is_sprite, pixel_data.palette) {
+                        (false, _) => PaletteRegister::Bgp,
+                        (true, 0) => PaletteRegister::Obp0,
+                        (true, _) => PaletteRegister::Obp1,
+                    };
+                }
+            }
+
             self.x += 1;
         }
 
-        // TODO: Implement the OBJ penalty algorithm, relevant link:
-        // https://gbdev.io/pandocs/Rendering.html
-
         if self.x == DISPLAY_SIZE_X as u8 {
             self.state = GpuState::HBlank;
             self.ticks = 0;
@@ -111,6 +165,12 @@ impl Gpu {
                     .iter_mut()
                     .for_each(|layer| layer.get_tile_step_2(self, bus));
 
+                // https://gbdev.io/pandocs/Rendering.html#mode-3-length
+                self.stalled_ticks += layers
+                    .iter()
+                    .map(|layer| layer.get_fetch_penalty(self, bus))
+                    .sum::<u16>();
+
                 self.cycle_state();
             }
         }
@@ -174,6 +234,9 @@ fn bytes_to_slice(low: u8, high: u8) -> Vec<PixelData> {
         pixel_data.push(PixelData {
             color: bools_to_color(high.get_bit(i as u8), low.get_bit(i as u8)),
             z_index: 0,
+            palette: 0,
+            is_sprite: false,
+            has_priority: false,
         });
     }
 
@@ -199,8 +262,13 @@ fn mix_with_z_index(below_slice: &[PixelData], above_slice: &[PixelData]) -> Vec
     let mut new_slice: Vec<PixelData> = Vec::new();
 
     for (below_pixel, above_pixel) in below_slice.iter().zip(above_slice) {
+        // On the GameBoy Color a background tile can ask to be drawn above the sprites,
+        // except for its Light pixels
+        let is_below_priority =
+            above_pixel.is_sprite && below_pixel.has_priority && below_pixel.color != Color::Light;
+
         // We select the pixel based on the higher z index
-        if above_pixel.z_index > below_pixel.z_index {
+        if above_pixel.z_index > below_pixel.z_index && !is_below_priority {
             new_slice.push(*above_pixel);
         } else {
             new_slice.push(*below_pixel);
@@ -214,9 +282,9 @@ fn mix_above_light(below_slice: &[PixelData], above_slice: &[PixelData]) -> Vec<
     let mut new_slice: Vec<PixelData> = Vec::new();
 
     for (below_pixel, above_pixel) in below_slice.iter().zip(above_slice) {
-        // The above sprite will only show when the below sprite is light, no need to
-        // check for priority
-        if below_pixel.color == Color::Light {
+        // The above sprite will only show when the below sprite is light, as long as it's
+        // not transparent itself
+        if below_pixel.color == Color::Light && above_pixel.z_index > below_pixel.z_index {
             new_slice.push(*above_pixel);
         } else {
             new_slice.push(*below_pixel);
@@ -230,4 +298,7 @@ fn mix_above_light(below_slice: &[PixelData], above_slice: &[PixelData]) -> Vec<
 pub(super) const EMPTY_SLICE: [PixelData; 8] = [PixelData {
     color: Color::Light,
     z_index: 0,
+    palette: 0,
+    is_sprite: false,
+    has_priority: false,
 }; 8];
@@ -1,12 +1,16 @@
 use crate::{
     bus::Bus,
     common::Bit,
-    consts::gpu::{LCDC, OBP0, OBP1},
+    consts::gpu::{LCDC, OBP0, OBP1, SCX},
     gpu::{pixel_transfer::bytes_to_slice, Color, Gpu, PixelData, Priority},
+    save_state::{SaveState, SaveStateError, StateReader, StateWriter},
 };
 
 use super::{bools_to_color, Layer, EMPTY_SLICE};
 
+/// The GPU only fetches the first 10 sprites in OAM that are on the line
+const MAX_SPRITES_PER_LINE: usize = 10;
+
 pub(crate) struct SpriteLayer {
     sprite_to_draw: Option<SpriteData>,
     rendered_sprites: u8,
@@ -16,6 +20,7 @@ pub(crate) struct SpriteLayer {
     // When a sprite is cut off because it's not on the 8x8 grid, the data that got cut
     // off is stored in these variables so we can push it out later
     leftover_palette: Palette,
+    leftover_cgb_palette: u8,
     leftover_low: u8,
     leftover_high: u8,
 
@@ -37,6 +42,7 @@ impl SpriteLayer {
             tile_data_low: 0,
             tile_data_high: 0,
             leftover_palette: Palette::OBP0,
+            leftover_cgb_palette: 0,
             leftover_low: 0,
             leftover_high: 0,
             left_side_shift: 0,
@@ -68,11 +74,7 @@ impl Layer for SpriteLayer {
     fn get_tile_step_2(&mut self, gpu: &Gpu, bus: &Bus) {
         self.sprite_to_draw = None;
 
-        // Sprites can either be 8 pixels high or 16 pixels high
-        let sprite_height: u8 = match bus.read(LCDC).get_bit(2) {
-            false => 8,
-            true => 16,
-        };
+        let sprite_height = get_sprite_height(bus);
 
         // TODO: This is kinda ugly
         for sprite in &gpu.sprites {
@@ -90,14 +92,14 @@ impl Layer for SpriteLayer {
                 self.is_sprite_left_side = true;
             }
 
-            // TODO: I don't know why it's specifically 7 and not 8, but if I put 8 in
-            // this it becomes very jittery
-            let sprite_x = sprite_to_draw.x.saturating_sub(7);
             let sprite_y = sprite_to_draw.y - 16;
 
-            if (sprite_x..(sprite_x.wrapping_add(8))).contains(&gpu.virtual_x)
-                && (sprite_y..(sprite_y + sprite_height)).contains(&gpu.y)
-            {
+            // On the GameBoy Color LCDC.0 clear means that sprites are always on top
+            if bus.is_cgb && !bus.read(LCDC).get_bit(0) {
+                sprite_to_draw.priority = Priority::TransparentLight;
+            }
+
+            if is_sprite_on_slice(sprite, sprite_height, gpu) {
                 // We are rendering the top of a tall sprite
                 if (sprite_y..(sprite_y + 8)).contains(&gpu.y) && sprite_height == 16 {
                     render_top_tall_sprite(&mut sprite_to_draw);
@@ -109,7 +111,9 @@ impl Layer for SpriteLayer {
                 }
 
                 self.sprite_to_draw = Some(match self.sprite_to_draw {
-                    Some(existing_sprite) => sprite_priority(existing_sprite, sprite_to_draw),
+                    Some(existing_sprite) => {
+                        sprite_priority(existing_sprite, sprite_to_draw, bus.is_cgb)
+                    }
                     None => sprite_to_draw,
                 });
             }
@@ -132,7 +136,7 @@ impl Layer for SpriteLayer {
             address ^= 0b0000_0000_0000_1110;
         }
 
-        let mut tile_data = bus.read(address);
+        let mut tile_data = bus.read_video_ram(sprite_to_draw.bank, address);
 
         // When horizontally flipping we have to reverse the read byte
         if sprite_to_draw.x_flip {
@@ -172,7 +176,12 @@ impl Layer for SpriteLayer {
                 (self.tile_data_high >> 8) as u8,
             );
 
-            apply_palette_to_slice(&mut leftover_slice, self.leftover_palette, bus);
+            apply_palette_to_slice(
+                &mut leftover_slice,
+                self.leftover_palette,
+                self.leftover_cgb_palette,
+                bus,
+            );
 
             self.leftover_low = 0;
             self.leftover_high = 0;
@@ -197,7 +206,13 @@ impl Layer for SpriteLayer {
         );
 
         self.leftover_palette = sprite_to_draw.palette;
-        apply_palette_to_slice(&mut slice, sprite_to_draw.palette, bus);
+        self.leftover_cgb_palette = sprite_to_draw.cgb_palette;
+        apply_palette_to_slice(
+            &mut slice,
+            sprite_to_draw.palette,
+            sprite_to_draw.cgb_palette,
+            bus,
+        );
 
         // The leftover bytes are just the parts of the slice we haven't rendered yet
         self.leftover_low = (self.tile_data_low >> 8) as u8;
@@ -207,6 +222,37 @@ impl Layer for SpriteLayer {
         slice
     }
 
+    /// Every sprite on the slice takes 6 dots to fetch. The first one can also wait for
+    /// the background fetcher to finish the tile it's working on, which takes up to 5
+    /// more dots depending on where the sprite is compared to the background tiles. Only
+    /// the sprites that OAM search would pick for the line are fetched
+    fn get_fetch_penalty(&self, gpu: &Gpu, bus: &Bus) -> u16 {
+        if !self.is_layer_enabled(bus) {
+            return 0;
+        }
+
+        let sprite_height = get_sprite_height(bus);
+        let mut penalty = 0;
+
+        for (i, sprite) in gpu
+            .sprites
+            .iter()
+            .filter(|sprite| is_sprite_on_line(sprite, sprite_height, gpu))
+            .take(MAX_SPRITES_PER_LINE)
+            .filter(|sprite| sprite.y >= 16 && is_sprite_on_slice(sprite, sprite_height, gpu))
+            .enumerate()
+        {
+            penalty += match (i, sprite.x) {
+                // A sprite on the left of the screen always waits for the whole tile
+                (_, 0) => 11,
+                (0, x) => 6 + 5u16.saturating_sub((x.wrapping_add(bus.read(SCX)) % 8) as u16),
+                _ => 6,
+            };
+        }
+
+        penalty
+    }
+
     fn at_hblank(&mut self, _bus: &Bus, _gpu: &Gpu) {
         self.rendered_sprites = 0;
 
@@ -219,8 +265,71 @@ impl Layer for SpriteLayer {
     }
 }
 
-/// Takes in a slice and colors it according to a palette
-fn apply_palette_to_slice(slice: &mut Vec<PixelData>, palette: Palette, bus: &Bus) {
+impl SaveState for SpriteLayer {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.sprite_to_draw.is_some());
+        if let Some(sprite_to_draw) = self.sprite_to_draw {
+            sprite_to_draw.save_state(writer);
+        }
+
+        writer.write_u8(self.rendered_sprites);
+        writer.write_u16(self.tile_data_low);
+        writer.write_u16(self.tile_data_high);
+        writer.write_u8(self.leftover_palette as u8);
+        writer.write_u8(self.leftover_cgb_palette);
+        writer.write_u8(self.leftover_low);
+        writer.write_u8(self.leftover_high);
+        writer.write_u8(self.left_side_shift);
+        writer.write_bool(self.is_sprite_left_side);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.sprite_to_draw = match reader.read_bool()? {
+            true => Some(SpriteData::load_state(reader)?),
+            false => None,
+        };
+
+        self.rendered_sprites = reader.read_u8()?;
+        self.tile_data_low = reader.read_u16()?;
+        self.tile_data_high = reader.read_u16()?;
+        self.leftover_palette = Palette::from_state(reader.read_u8()?)?;
+        self.leftover_cgb_palette = reader.read_u8()?;
+        self.leftover_low = reader.read_u8()?;
+        self.leftover_high = reader.read_u8()?;
+        self.left_side_shift = reader.read_u8()?;
+        self.is_sprite_left_side = reader.read_bool()?;
+
+        Ok(())
+    }
+}
+
+/// Takes in a slice and colors it according to a palette, on the GameBoy Color the
+/// colors are picked later from `cgb_palette`
+fn apply_palette_to_slice(
+    slice: &mut Vec<PixelData>,
+    palette: Palette,
+    cgb_palette: u8,
+    bus: &Bus,
+) {
+    if bus.is_cgb {
+        for pixel_data in slice {
+            if pixel_data.color != Color::Light {
+                pixel_data.z_index = 2;
+            }
+
+            pixel_data.palette = cgb_palette;
+            pixel_data.is_sprite = true;
+        }
+
+        return;
+    }
+
+    // Remembered so that the frontend can give each palette its own colors
+    let palette_index = match palette {
+        Palette::OBP0 => 0,
+        Palette::OBP1 => 1,
+    };
+
     let palette = match palette {
         Palette::OBP0 => bus.read(OBP0),
         Palette::OBP1 => bus.read(OBP1),
@@ -237,6 +346,9 @@ fn apply_palette_to_slice(slice: &mut Vec<PixelData>, palette: Palette, bus: &Bu
             pixel_data.z_index = 2;
         }
 
+        pixel_data.palette = palette_index;
+        pixel_data.is_sprite = true;
+
         // Sprites can still show Light pixels by mapping another color to Light
         pixel_data.color = match pixel_data.color {
             Color::Light => Color::Light,
@@ -247,6 +359,32 @@ fn apply_palette_to_slice(slice: &mut Vec<PixelData>, palette: Palette, bus: &Bu
     }
 }
 
+/// Sprites can either be 8 pixels high or 16 pixels high
+fn get_sprite_height(bus: &Bus) -> u8 {
+    match bus.read(LCDC).get_bit(2) {
+        false => 8,
+        true => 16,
+    }
+}
+
+/// If any row of the sprite is on the current line, wherever it is horizontally. The Y
+/// coordinate is 16 pixels below the top of the sprite, so it can be partly off the top
+fn is_sprite_on_line(sprite: &SpriteData, sprite_height: u8, gpu: &Gpu) -> bool {
+    let line = gpu.y as u16 + 16;
+    (sprite.y as u16..sprite.y as u16 + sprite_height as u16).contains(&line)
+}
+
+/// If the sprite is on the current line and it starts in the slice being fetched
+fn is_sprite_on_slice(sprite: &SpriteData, sprite_height: u8, gpu: &Gpu) -> bool {
+    // TODO: I don't know why it's specifically 7 and not 8, but if I put 8 in this it
+    // becomes very jittery
+    let sprite_x = sprite.x.saturating_sub(7);
+    let sprite_y = sprite.y - 16;
+
+    (sprite_x..(sprite_x.wrapping_add(8))).contains(&gpu.virtual_x)
+        && (sprite_y..(sprite_y + sprite_height)).contains(&gpu.y)
+}
+
 /// This functions gets called before rendering the top of a 16 pixel high sprite
 fn render_top_tall_sprite(sprite: &mut SpriteData) {
     // ... and when it is, the sprite's bottom bit must be set to 0, and 1 if y flipping
@@ -265,9 +403,10 @@ fn render_bottom_tall_sprite(sprite: &mut SpriteData) {
     };
 }
 
-/// Returns the sprite with the highest priority
-fn sprite_priority(sprite1: SpriteData, sprite2: SpriteData) -> SpriteData {
-    if sprite1.x < sprite2.x {
+/// Returns the sprite with the highest priority, `sprite2` always comes first in OAM. On
+/// the GameBoy Color only the position in OAM matters
+fn sprite_priority(sprite1: SpriteData, sprite2: SpriteData, is_cgb: bool) -> SpriteData {
+    if sprite1.x < sprite2.x && !is_cgb {
         return sprite1;
     }
 
@@ -283,6 +422,10 @@ pub(crate) struct SpriteData {
     pub(crate) palette: Palette,
     pub(crate) x_flip: bool,
     pub(crate) y_flip: bool,
+
+    /// Only used on the GameBoy Color, which has 8 object palettes and 2 VRAM banks
+    pub(crate) cgb_palette: u8,
+    pub(crate) bank: u8,
 }
 
 #[derive(Clone, Copy)]
@@ -290,3 +433,41 @@ pub(crate) enum Palette {
     OBP0,
     OBP1,
 }
+
+impl SpriteData {
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.y);
+        writer.write_u8(self.x);
+        writer.write_u8(self.tile_number);
+        writer.write_u8(self.priority as u8);
+        writer.write_u8(self.palette as u8);
+        writer.write_bool(self.x_flip);
+        writer.write_bool(self.y_flip);
+        writer.write_u8(self.cgb_palette);
+        writer.write_u8(self.bank);
+    }
+
+    pub(crate) fn load_state(reader: &mut StateReader) -> Result<Self, SaveStateError> {
+        Ok(Self {
+            y: reader.read_u8()?,
+            x: reader.read_u8()?,
+            tile_number: reader.read_u8()?,
+            priority: Priority::from_state(reader.read_u8()?)?,
+            palette: Palette::from_state(reader.read_u8()?)?,
+            x_flip: reader.read_bool()?,
+            y_flip: reader.read_bool()?,
+            cgb_palette: reader.read_u8()?,
+            bank: reader.read_u8()?,
+        })
+    }
+}
+
+impl Palette {
+    fn from_state(value: u8) -> Result<Self, SaveStateError> {
+        match value {
+            0 => Ok(Palette::OBP0),
+            1 => Ok(Palette::OBP1),
+            _ => Err(SaveStateError::Corrupted),
+        }
+    }
+}
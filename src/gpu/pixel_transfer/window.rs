@@ -6,6 +6,7 @@ use crate::{
         gpu::{LCDC, WX, WY},
     },
     gpu::{Gpu, PixelData, Priority},
+    save_state::{SaveState, SaveStateError, StateReader, StateWriter},
 };
 
 use super::{bytes_to_slice, vuza_gate, Layer, EMPTY_SLICE};
@@ -16,6 +17,9 @@ pub(crate) struct WindowLayer {
     tile_data_low: u8,
     tile_data_high: u8,
 
+    /// The attributes of the tile on the GameBoy Color, like the background
+    attributes: u8,
+
     /// The window has an internal line counter, when the window is turned off and then
     /// turned on later, it will continue as if there was no interruption, contrary to the
     /// background
@@ -29,6 +33,7 @@ impl WindowLayer {
             tile_id: 0,
             tile_data_low: 0,
             tile_data_high: 0,
+            attributes: 0,
             window_ly: 0,
         }
     }
@@ -63,6 +68,7 @@ impl Layer for WindowLayer {
 
         if window_x.is_negative() || window_y.is_negative() {
             self.tile_id = 0;
+            self.attributes = 0;
             return;
         }
 
@@ -72,11 +78,15 @@ impl Layer for WindowLayer {
             | (window_y as u16 / 8) << 5
             | window_x as u16 / 8;
 
-        self.tile_id = bus.read(address);
+        self.tile_id = bus.read_video_ram(0, address);
+        self.attributes = match bus.is_cgb {
+            true => bus.read_video_ram(1, address),
+            false => 0,
+        };
     }
 
     fn get_tile_data(&mut self, is_high_part: bool, gpu: &Gpu, bus: &Bus) {
-        let window_y: i32 = gpu.y as i32 - bus.read(WY) as i32;
+        let mut window_y: i32 = gpu.y as i32 - bus.read(WY) as i32;
 
         if window_y.is_negative() {
             self.tile_data_low = 0;
@@ -84,6 +94,10 @@ impl Layer for WindowLayer {
             return;
         }
 
+        if self.attributes.get_bit(6) {
+            window_y = 7 - window_y % 8;
+        }
+
         // https://github.com/ISSOtm/pandocs/blob/rendering-internals/src/Rendering_Internals.md#get-tile-row-low
         let address = 0b100 << 13
             | vuza_gate(bus.read(LCDC), self.tile_id) << 12
@@ -91,9 +105,16 @@ impl Layer for WindowLayer {
             | (window_y as u16 % 8) << 1
             | is_high_part as u16;
 
+        let mut tile_data = bus.read_video_ram(self.attributes.get_bit(3) as u8, address);
+
+        // When horizontally flipping we have to reverse the read byte
+        if self.attributes.get_bit(5) {
+            tile_data = tile_data.reverse_bits();
+        }
+
         match is_high_part {
-            false => self.tile_data_low = bus.read(address),
-            true => self.tile_data_high = bus.read(address),
+            false => self.tile_data_low = tile_data,
+            true => self.tile_data_high = tile_data,
         }
     }
 
@@ -111,11 +132,23 @@ impl Layer for WindowLayer {
         // The window needs to be rendered above the background
         for pixel in &mut slice {
             pixel.z_index = 1;
+            pixel.palette = self.attributes & 0b111;
+            pixel.has_priority = self.attributes.get_bit(7) && bus.read(LCDC).get_bit(0);
         }
 
         slice
     }
 
+    /// When the window starts the fetcher has to start over with the window tiles
+    fn get_fetch_penalty(&self, gpu: &Gpu, bus: &Bus) -> u16 {
+        let is_window_starting = bus.read(WX) as usize + 8 > gpu.virtual_x as usize;
+
+        match self.is_layer_enabled(bus) && is_window_being_rendered(bus, gpu) {
+            true if is_window_starting => 6,
+            _ => 0,
+        }
+    }
+
     fn at_hblank(&mut self, bus: &Bus, _gpu: &Gpu) {
         if is_window_in_bounds(bus) {
             self.window_ly = self.window_ly.wrapping_add(1);
@@ -127,6 +160,28 @@ impl Layer for WindowLayer {
     }
 }
 
+impl SaveState for WindowLayer {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.lcdc_6);
+        writer.write_u8(self.tile_id);
+        writer.write_u8(self.tile_data_low);
+        writer.write_u8(self.tile_data_high);
+        writer.write_u8(self.attributes);
+        writer.write_u8(self.window_ly);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.lcdc_6 = reader.read_bool()?;
+        self.tile_id = reader.read_u8()?;
+        self.tile_data_low = reader.read_u8()?;
+        self.tile_data_high = reader.read_u8()?;
+        self.attributes = reader.read_u8()?;
+        self.window_ly = reader.read_u8()?;
+
+        Ok(())
+    }
+}
+
 /// If the windows is currently being rendered at the current position
 fn is_window_being_rendered(bus: &Bus, gpu: &Gpu) -> bool {
     if bus.read(WX) as usize > gpu.virtual_x as usize {
@@ -0,0 +1,60 @@
+//! STAT tells the game what the GPU is doing: bits 0-1 are the current mode and bit 2 is
+//! set when LY == LYC. Bits 3-6 choose which of those conditions request the STAT
+//! interrupt.
+//!
+//! The conditions are OR-ed together in a single line, and the interrupt is requested
+//! only when that line goes from low to high. So if a condition is already true when
+//! another one becomes true, no new interrupt is requested, this is called "STAT
+//! blocking".
+//!
+//! This is a really good resource: https://gbdev.io/pandocs/STAT.html
+
+use crate::{
+    bus::Bus,
+    common::Bit,
+    consts::{
+        bus::IO_START,
+        cpu::IF,
+        gpu::{LY, LYC, STAT},
+    },
+};
+
+use super::{Gpu, GpuState};
+
+impl GpuState {
+    /// The mode number as seen in STAT
+    fn mode(self) -> u8 {
+        match self {
+            GpuState::HBlank => 0,
+            GpuState::VBlank => 1,
+            GpuState::OamSearch => 2,
+            GpuState::PixelTransfer => 3,
+        }
+    }
+}
+
+impl Gpu {
+    /// Updates the read only bits of STAT and requests the STAT interrupt, this needs to
+    /// be called every dot after LY is updated
+    pub(super) fn update_stat(&mut self, bus: &mut Bus) {
+        let state = self.get_visible_state();
+        let is_coincidence = bus.read(LY) == bus.read(LYC);
+
+        // STAT is written directly since the game can't write to the lower 3 bits, bit 7
+        // doesn't exist and always reads as 1
+        let mut stat = bus.read(STAT) & 0b01111000;
+        stat |= 0b10000000 | (is_coincidence as u8) << 2 | state.mode();
+        bus.io[(STAT - IO_START as u16) as usize] = stat;
+
+        let stat_line = (stat.get_bit(3) && state == GpuState::HBlank)
+            || (stat.get_bit(4) && state == GpuState::VBlank)
+            || (stat.get_bit(5) && state == GpuState::OamSearch)
+            || (stat.get_bit(6) && is_coincidence);
+
+        if stat_line && !self.stat_line {
+            bus.write(IF, bus.read(IF) | 0b00000010); // Enabling stat interrupt
+        }
+
+        self.stat_line = stat_line;
+    }
+}
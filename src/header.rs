@@ -0,0 +1,195 @@
+//! Every cartridge has a header from 0x100 to 0x14F that describes the game and the
+//! hardware inside the cartridge
+
+use crate::common::merge_two_u8s_into_u16;
+
+/// The header ends at 0x14F, a rom smaller than this can't tell us what cartridge it is
+pub(crate) const HEADER_END: usize = 0x150;
+
+/// Whether or not the game uses the GameBoy Color features
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CgbFlag {
+    /// The game was made for the original GameBoy
+    DmgOnly,
+
+    /// The game uses color when it can, but it also works on the original GameBoy
+    CgbCompatible,
+
+    /// The game only works on the GameBoy Color
+    CgbOnly,
+}
+
+/// Where the game was meant to be sold
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Destination {
+    Japan,
+    Overseas,
+}
+
+#[derive(Clone, Debug)]
+pub struct CartridgeHeader {
+    /// The name of the game in uppercase ASCII, the unused bytes are removed
+    pub title: String,
+
+    /// Newer cartridges use 4 bytes of the title for this, older ones don't have it
+    pub manufacturer_code: Option<String>,
+
+    pub cgb_flag: CgbFlag,
+
+    /// The Super GameBoy only enables its functions if this is set and the old licensee
+    /// code is 0x33
+    pub supports_sgb: bool,
+
+    /// The company that published the game, when this is 0x33 the new licensee code is
+    /// used instead
+    pub old_licensee_code: u8,
+
+    /// Two ASCII characters, only present when the old licensee code is 0x33
+    pub new_licensee_code: Option<String>,
+
+    /// Which MBC and extra hardware (ram, battery, clock...) the cartridge has
+    pub cartridge_type: u8,
+
+    pub rom_size_byte: u8,
+    pub ram_size_byte: u8,
+    pub destination: Destination,
+    pub version: u8,
+
+    /// A checksum of the bytes from 0x134 to 0x14C, the boot rom locks up if it's wrong
+    pub header_checksum: u8,
+
+    /// A checksum of the whole rom (except these 2 bytes), nothing ever checks this one
+    pub global_checksum: u16,
+
+    // What the checksums should be, calculated from the rom itself
+    calculated_header_checksum: u8,
+    calculated_global_checksum: u16,
+}
+
+impl CartridgeHeader {
+    /// Parses the header of `rom`, if the rom is too small the missing bytes are treated
+    /// as 0
+    pub fn new(rom: &[u8]) -> Self {
+        let mut header = [0u8; HEADER_END];
+        let header_size = rom.len().min(HEADER_END);
+        header[..header_size].copy_from_slice(&rom[..header_size]);
+
+        let cgb_flag = match header[0x143] {
+            0xC0 => CgbFlag::CgbOnly,
+            value if value & 0x80 != 0 => CgbFlag::CgbCompatible,
+            _ => CgbFlag::DmgOnly,
+        };
+
+        // The manufacturer code only exists on cartridges made after the GameBoy Color,
+        // before that the title could use all 16 bytes
+        let manufacturer_code = &header[0x13F..0x143];
+        let has_manufacturer_code = cgb_flag != CgbFlag::DmgOnly
+            && manufacturer_code
+                .iter()
+                .all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit());
+
+        let title_end = match (has_manufacturer_code, cgb_flag) {
+            (true, _) => 0x13F,
+            (false, CgbFlag::DmgOnly) => 0x144,
+            (false, _) => 0x143,
+        };
+
+        let old_licensee_code = header[0x14B];
+
+        Self {
+            title: bytes_to_string(&header[0x134..title_end]),
+            manufacturer_code: has_manufacturer_code.then(|| bytes_to_string(manufacturer_code)),
+            cgb_flag,
+            supports_sgb: header[0x146] == 0x03,
+            old_licensee_code,
+            new_licensee_code: (old_licensee_code == 0x33)
+                .then(|| bytes_to_string(&header[0x144..0x146])),
+            cartridge_type: header[0x147],
+            rom_size_byte: header[0x148],
+            ram_size_byte: header[0x149],
+            destination: match header[0x14A] {
+                0x00 => Destination::Japan,
+                _ => Destination::Overseas,
+            },
+            version: header[0x14C],
+            header_checksum: header[0x14D],
+            global_checksum: merge_two_u8s_into_u16(header[0x14E], header[0x14F]),
+            calculated_header_checksum: calculate_header_checksum(&header),
+            calculated_global_checksum: calculate_global_checksum(rom),
+        }
+    }
+
+    /// The rom size in bytes
+    pub fn rom_size(&self) -> usize {
+        // This formula is from the pandocs
+        0x8000 << (self.rom_size_byte as usize).min(8)
+    }
+
+    /// The external ram size in bytes, this does not include the ram that's built in the
+    /// MBC2
+    pub fn ram_size(&self) -> usize {
+        // This formula is from the pandocs
+        match self.ram_size_byte {
+            2 => 0x2000,
+            3 => 0x2000 * 4,
+            4 => 0x2000 * 16,
+            5 => 0x2000 * 8,
+            _ => 0,
+        }
+    }
+
+    /// Wheter or not the cartridge type has a battery, which keeps the ram (and the
+    /// clock) alive when the GameBoy is off
+    pub fn has_battery(&self) -> bool {
+        matches!(
+            self.cartridge_type,
+            0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFF
+        )
+    }
+
+    pub fn is_header_checksum_valid(&self) -> bool {
+        self.header_checksum == self.calculated_header_checksum
+    }
+
+    pub fn is_global_checksum_valid(&self) -> bool {
+        self.global_checksum == self.calculated_global_checksum
+    }
+
+    /// A header is valid if its checksum is right, the global checksum is not taken into
+    /// account because the real hardware ignores it too
+    pub fn is_valid(&self) -> bool {
+        self.is_header_checksum_valid()
+    }
+}
+
+/// Titles are padded with zeros, and some games put garbage in there, so we stop at the
+/// first zero and replace anything that's not printable
+fn bytes_to_string(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .take_while(|&&byte| byte != 0)
+        .map(|&byte| match byte.is_ascii_graphic() || byte == b' ' {
+            true => byte as char,
+            false => '?',
+        })
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+/// This is the same algorithm the boot rom uses
+fn calculate_header_checksum(header: &[u8]) -> u8 {
+    header[0x134..=0x14C].iter().fold(0u8, |checksum, &byte| {
+        checksum.wrapping_sub(byte).wrapping_sub(1)
+    })
+}
+
+/// The sum of every byte in the rom except the checksum itself
+fn calculate_global_checksum(rom: &[u8]) -> u16 {
+    rom.iter()
+        .enumerate()
+        .filter(|(address, _)| *address != 0x14E && *address != 0x14F)
+        .fold(0u16, |checksum, (_, &byte)| {
+            checksum.wrapping_add(byte as u16)
+        })
+}
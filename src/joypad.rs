@@ -1,4 +1,4 @@
-use crate::{bus::Bus, common::Bit, consts::joypad::JOYP};
+use crate::common::Bit;
 
 pub struct Joypad {
     pub is_a_pressed: bool,
@@ -27,32 +27,21 @@ impl Joypad {
 }
 
 impl Joypad {
-    pub(crate) fn to_byte(&self, bus: &Bus) -> u8 {
-        // The lower nibble is read only and contains button data
-        let mut joyp = bus.read(JOYP) & 0b11110000;
+    /// Packs the buttons in a byte, the lower nibble is the directional pad (right, left,
+    /// up, down) and the higher nibble the other buttons (A, B, select, start). Unlike
+    /// JOYP, 1 means pressed
+    pub(crate) fn pressed_buttons(&self) -> u8 {
+        let mut pressed_buttons = 0;
 
-        // The higher nibble instead, is used to select which "group" of buttons to listen
-        let are_buttons_enabled = joyp.get_bit(5);
-        let is_dpad_enabled = joyp.get_bit(4);
+        pressed_buttons.set_bit(0, self.is_right_pressed);
+        pressed_buttons.set_bit(1, self.is_left_pressed);
+        pressed_buttons.set_bit(2, self.is_up_pressed);
+        pressed_buttons.set_bit(3, self.is_down_pressed);
+        pressed_buttons.set_bit(4, self.is_a_pressed);
+        pressed_buttons.set_bit(5, self.is_b_pressed);
+        pressed_buttons.set_bit(6, self.is_select_pressed);
+        pressed_buttons.set_bit(7, self.is_start_pressed);
 
-        // Bit 5 tells the GameBoy to listen to the buttons
-        if are_buttons_enabled && !is_dpad_enabled {
-            joyp |= self.is_right_pressed as u8;
-            joyp |= (self.is_left_pressed as u8) << 1;
-            joyp |= (self.is_up_pressed as u8) << 2;
-            joyp |= (self.is_down_pressed as u8) << 3;
-        }
-
-        // Bit 4 tells the GameBoy to listen to the directional pad
-        if is_dpad_enabled && !are_buttons_enabled {
-            joyp |= self.is_a_pressed as u8;
-            joyp |= (self.is_b_pressed as u8) << 1;
-            joyp |= (self.is_select_pressed as u8) << 2;
-            joyp |= (self.is_start_pressed as u8) << 3;
-        }
-
-        // For some reason in the gameboy 0 means pressed and 1 means not pressed, so we
-        // need to invert the lower nibble
-        joyp ^ 0b00001111
+        pressed_buttons
     }
 }
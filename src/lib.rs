@@ -1,75 +1,248 @@
 #![forbid(unsafe_code)]
 
-use bus::{Bus, BusError};
-use consts::joypad::JOYP;
-use cpu::Cpu;
+use apu::Apu;
+use bus::{new_mbc, new_mbc_unchecked, read_rom_file, Bus, BusError, Mbc};
+use consts::sgb::{SGB_FRAME_SIZE_X, SGB_FRAME_SIZE_Y};
+use cpu::{
+    cpu_bus::{CpuBus, TickingBus},
+    Cpu,
+};
 use flags::Flags;
 use gpu::{
     pixel_transfer::{
         background::BackgroundLayer, sprite::SpriteLayer, window::WindowLayer, Layers,
     },
-    Gpu, GpuState,
+    Gpu,
 };
+use header::{CartridgeHeader, CgbFlag};
 use joypad::Joypad;
+use palette::{Palette, Rgb};
 use registers::Registers;
+use rtc::TimeSource;
+use save_state::{SaveState, SaveStateError, StateReader, StateWriter};
+use serial::Serial;
+use timer::Timer;
 
+pub mod apu;
 mod bus;
 pub mod common;
 pub mod consts;
 mod cpu;
 pub mod flags;
 pub mod gpu;
+pub mod header;
 mod joypad;
+pub mod palette;
 pub mod registers;
+pub mod rtc;
+pub mod save_state;
+pub mod serial;
+mod sgb;
+mod timer;
 
 pub struct GameBoy {
+    pub apu: Apu,
     pub bus: Bus,
     pub cpu: Cpu,
     pub flags: Flags,
     pub gpu: Gpu,
     pub joypad: Joypad,
     pub registers: Registers,
+    pub serial: Serial,
+    pub timer: Timer,
 
     /// The layers are completely decoupled from the other parts of the emulator
     layers: Layers,
+
+    header: CartridgeHeader,
+
+    /// See `set_cycle_accurate`
+    is_cycle_accurate: bool,
 }
 
 impl GameBoy {
+    /// Loads a rom from a file, the rom header is checked and the rom is not loaded if
+    /// the cartridge type is not supported or the header does not match the rom
     pub fn new(rom_path: &str) -> Result<Self, BusError> {
-        Ok(Self {
-            bus: Bus::new(rom_path)?,
-            cpu: Cpu::new(),
-            flags: Flags::new(),
-            gpu: Gpu::new(),
-            joypad: Joypad::new(),
-            registers: Registers::new(),
-            layers: [
-                Box::new(BackgroundLayer::new()),
-                Box::new(WindowLayer::new()),
-                Box::new(SpriteLayer::new()),
-            ],
-        })
+        Self::new_from_rom_array(read_rom_file(rom_path)?)
     }
 
-    pub fn new_from_rom_array(rom: Vec<u8>) -> Self {
+    /// Like `new` but the rom is given directly
+    pub fn new_from_rom_array(rom: Vec<u8>) -> Result<Self, BusError> {
+        let header = CartridgeHeader::new(&rom);
+        let mbc = new_mbc(rom, &header)?;
+        Ok(Self::new_from_mbc(header, mbc))
+    }
+
+    /// Like `new` but the rom header is not checked, this is useful for homebrew roms,
+    /// which often have a wrong header. Unknown cartridge types are treated as if they
+    /// had no MBC
+    pub fn new_unchecked(rom_path: &str) -> Result<Self, BusError> {
+        Ok(Self::new_from_rom_array_unchecked(read_rom_file(rom_path)?))
+    }
+
+    /// Like `new_unchecked` but the rom is given directly
+    pub fn new_from_rom_array_unchecked(rom: Vec<u8>) -> Self {
+        let header = CartridgeHeader::new(&rom);
+        let mbc = new_mbc_unchecked(rom, &header);
+        Self::new_from_mbc(header, mbc)
+    }
+
+    /// Games that support the GameBoy Color run in CGB mode, the others run like on the
+    /// original GameBoy
+    fn new_from_mbc(header: CartridgeHeader, mbc: Box<dyn Mbc>) -> Self {
+        let is_cgb = header.cgb_flag != CgbFlag::DmgOnly;
+        let is_sgb = !is_cgb && header.supports_sgb && header.old_licensee_code == 0x33;
+
         Self {
-            bus: Bus::new_from_rom_array(rom),
+            apu: Apu::new(),
+            bus: Bus::new(mbc, is_cgb, is_sgb),
             cpu: Cpu::new(),
-            flags: Flags::new(),
+            flags: match is_cgb {
+                true => Flags::new_cgb(),
+                false => Flags::new(),
+            },
             gpu: Gpu::new(),
             joypad: Joypad::new(),
-            registers: Registers::new(),
+            registers: match is_cgb {
+                true => Registers::new_cgb(),
+                false => Registers::new(),
+            },
+            serial: Serial::new(),
+            timer: Timer::new(),
             layers: [
                 Box::new(BackgroundLayer::new()),
                 Box::new(WindowLayer::new()),
                 Box::new(SpriteLayer::new()),
             ],
+            header,
+            is_cycle_accurate: false,
+        }
+    }
+
+    /// Runs a DMG boot rom before the game, like the real hardware does. The boot rom
+    /// scrolls the logo down and then gives control to the game by itself. This has to
+    /// be called before running anything, because it puts the GameBoy back in the state
+    /// it's in when it's turned on
+    pub fn load_boot_rom(&mut self, boot_rom: &[u8]) -> Result<(), BusError> {
+        self.bus.load_boot_rom(boot_rom)?;
+        self.registers = Registers::new_before_boot();
+        self.flags = Flags::new_before_boot();
+        self.timer = Timer::new_before_boot();
+
+        Ok(())
+    }
+
+    /// Whether or not the game runs in GameBoy Color mode, the colors of the screen are in
+    /// `Gpu::cgb_screen` instead of `Gpu::screen`
+    pub fn is_cgb(&self) -> bool {
+        self.bus.is_cgb
+    }
+
+    /// Whether or not the game runs on a Super GameBoy, which happens when the game
+    /// supports it and it doesn't run in GameBoy Color mode
+    pub fn is_sgb(&self) -> bool {
+        self.bus.sgb.is_some()
+    }
+
+    /// The screen colored by the Super GameBoy with the border around it, every color is
+    /// in the RGB555 format like `Gpu::cgb_screen`. It's drawn at the end of every frame
+    pub fn sgb_frame(&self) -> Option<&[[u16; SGB_FRAME_SIZE_X]; SGB_FRAME_SIZE_Y]> {
+        self.bus.sgb.as_ref().map(|sgb| sgb.frame())
+    }
+
+    /// The screen as RGBA8, 4 bytes for every pixel going left to right and top to bottom,
+    /// ready to be copied into a texture. In GameBoy Color mode the game picks its own
+    /// colors, so `palette` is ignored
+    pub fn screen_rgba8(&self, palette: &Palette) -> Vec<u8> {
+        self.screen_colors(palette)
+            .flat_map(Rgb::to_rgba8)
+            .collect()
+    }
+
+    /// Like `screen_rgba8`, but every pixel is a single RGB565 value
+    pub fn screen_rgb565(&self, palette: &Palette) -> Vec<u16> {
+        self.screen_colors(palette).map(Rgb::to_rgb565).collect()
+    }
+
+    /// Like `screen_rgba8` but for `sgb_frame`, the Super GameBoy has its own colors
+    pub fn sgb_frame_rgba8(&self) -> Option<Vec<u8>> {
+        self.sgb_frame().map(|frame| {
+            frame
+                .iter()
+                .flatten()
+                .flat_map(|&color| Rgb::from_rgb555(color).to_rgba8())
+                .collect()
+        })
+    }
+
+    /// Like `screen_rgb565` but for `sgb_frame`
+    pub fn sgb_frame_rgb565(&self) -> Option<Vec<u16>> {
+        self.sgb_frame().map(|frame| {
+            frame
+                .iter()
+                .flatten()
+                .map(|&color| Rgb::from_rgb555(color).to_rgb565())
+                .collect()
+        })
+    }
+
+    fn screen_colors<'a>(&'a self, palette: &'a Palette) -> Box<dyn Iterator<Item = Rgb> + 'a> {
+        match self.is_cgb() {
+            true => Box::new(
+                self.gpu
+                    .cgb_screen
+                    .iter()
+                    .flatten()
+                    .map(|&color| Rgb::from_rgb555(color)),
+            ),
+            false => Box::new(
+                self.gpu
+                    .screen
+                    .iter()
+                    .flatten()
+                    .zip(self.gpu.screen_palettes.iter().flatten())
+                    .map(|(&color, &register)| palette.get_color(color, register)),
+            ),
         }
     }
 
+    /// The information about the game and the cartridge hardware found in the rom
+    pub fn header(&self) -> &CartridgeHeader {
+        &self.header
+    }
+
     /// Parse and run the next opcode, and tick the other pieces of hardware
     pub fn step(&mut self) {
+        // In the low power mode nothing runs, not even the LCD and the timer, until a
+        // button is pressed
+        if self.cpu.stopped {
+            self.bus.set_pressed_buttons(self.joypad.pressed_buttons());
+            self.cpu.stopped = !self.bus.is_selected_button_pressed();
+            return;
+        }
+
+        // The CPU doesn't run while the VRAM DMA copies a block, the rest of the hardware
+        // keeps going
+        if self.bus.vram_dma.is_copying() {
+            self.ticking_bus().tick();
+            return;
+        }
+
+        match self.is_cycle_accurate {
+            true => self.step_cycle_accurate(),
+            false => self.step_batched(),
+        }
+
+        // JOYPAD
+        self.bus.set_pressed_buttons(self.joypad.pressed_buttons());
+    }
+
+    /// Runs the whole instruction and then ticks the other pieces of hardware for as many
+    /// cycles as it took
+    fn step_batched(&mut self) {
         let opcode = self.bus.next(0, &self.registers);
+        self.cpu.apply_halt_bug(&mut self.registers);
 
         // CPU - Opcodes
         let (bytes, cycles) =
@@ -79,43 +252,170 @@ impl GameBoy {
         self.registers.pc = self.registers.pc.wrapping_add(bytes as u16);
 
         // CPU - Interrupts
-        self.cpu
+        let interrupt_cycles = self
+            .cpu
             .execute_interrupts(&mut self.registers, &mut self.bus);
 
-        // CPU - OAM DMA Transfer
-        if self.bus.needs_to_dispatch_oam_dma {
-            self.bus.dispatch_oam_transfer();
-            self.bus.needs_to_dispatch_oam_dma = false;
+        // Timer, GPU, APU and serial
+        let mut ticking_bus = self.ticking_bus();
+        for _ in 0..cycles + interrupt_cycles {
+            ticking_bus.tick();
+        }
+    }
+
+    /// The other pieces of hardware are ticked on every memory access of the CPU, and on
+    /// the cycles where the CPU does something internally
+    fn step_cycle_accurate(&mut self) {
+        // The fields are borrowed one by one, so that the CPU can use the rest
+        let mut ticking_bus = TickingBus {
+            bus: &mut self.bus,
+            gpu: &mut self.gpu,
+            layers: &mut self.layers,
+            apu: &mut self.apu,
+            serial: &mut self.serial,
+            timer: &mut self.timer,
+            cycles: 0,
+        };
+
+        let opcode = ticking_bus.next(0, &self.registers);
+        self.cpu.apply_halt_bug(&mut self.registers);
+
+        // CPU - Opcodes
+        let (bytes, cycles) = self.cpu.interpret_opcode(
+            opcode,
+            &mut self.flags,
+            &mut self.registers,
+            &mut ticking_bus,
+        );
+
+        self.registers.pc = self.registers.pc.wrapping_add(bytes as u16);
+
+        // Most internal cycles happen at the end of the instruction, so they are not
+        // ticked by the instruction itself
+        while ticking_bus.cycles < cycles {
+            ticking_bus.tick();
         }
 
-        // CPU - Timer registers
-        self.cpu.update_div_register(&mut self.bus, cycles);
-        self.cpu.update_tima_register(&mut self.bus, cycles);
-
-        // GPU
-        for _ in 0..(cycles * 4) {
-            // A GPU tick is 1/4 of a cycle, so it needs to be called 4 times for every
-            // cycle. TODO: This is not actually all that accurate to the actual GameBoy
-            // but to change this I will have to redesign the CPU to count cycles
-            // procedurally and call `Gpu.tick()` for every cycle from there
-            self.gpu.tick(&mut self.layers, &mut self.bus);
+        // CPU - Interrupts
+        self.cpu
+            .execute_interrupts(&mut self.registers, &mut ticking_bus);
+    }
+
+    fn ticking_bus(&mut self) -> TickingBus<'_> {
+        TickingBus {
+            bus: &mut self.bus,
+            gpu: &mut self.gpu,
+            layers: &mut self.layers,
+            apu: &mut self.apu,
+            serial: &mut self.serial,
+            timer: &mut self.timer,
+            cycles: 0,
         }
+    }
 
-        // JOYPAD
-        self.bus.write(JOYP, self.joypad.to_byte(&self.bus));
+    /// In the cycle accurate mode the timer, the GPU, the APU and the serial port are
+    /// ticked in between the memory accesses of the CPU, instead of after the whole
+    /// instruction. This is needed by some games and test roms that rely on exact timings,
+    /// but it's slower so it's off by default
+    pub fn set_cycle_accurate(&mut self, is_cycle_accurate: bool) {
+        self.is_cycle_accurate = is_cycle_accurate;
+    }
+
+    pub fn is_cycle_accurate(&self) -> bool {
+        self.is_cycle_accurate
+    }
+
+    /// Returns the battery backed ram of the cartridge, frontends should write this to a
+    /// `.sav` file when closing the game. This is `None` if the cartridge has no battery
+    pub fn get_save_data(&self) -> Option<Vec<u8>> {
+        self.bus.mbc.get_save_data()
+    }
+
+    /// Loads the data returned by `get_save_data`, or a `.sav` file from another emulator.
+    /// This should be called before running the game
+    pub fn load_save_data(&mut self, data: &[u8]) -> Result<(), BusError> {
+        self.bus.mbc.load_save_data(data)
+    }
+
+    /// Wheter or not the cartridge's rumble motor is currently on
+    pub fn is_rumbling(&self) -> bool {
+        self.bus.mbc.is_rumbling()
+    }
+
+    /// Replaces where the cartridge's real time clock gets the time from, this does
+    /// nothing if the cartridge has no clock
+    pub fn set_rtc_time_source(&mut self, time_source: Box<dyn TimeSource>) {
+        self.bus.mbc.set_time_source(time_source);
+    }
+
+    /// Takes a snapshot of the whole GameBoy, which can be restored with `load_state`
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new(self.rom_checksum());
+
+        self.cpu.save_state(&mut writer);
+        self.timer.save_state(&mut writer);
+        self.registers.save_state(&mut writer);
+        self.flags.save_state(&mut writer);
+        self.bus.save_state(&mut writer);
+        self.gpu.save_state(&mut writer);
+        self.layers
+            .iter()
+            .for_each(|layer| layer.save_state(&mut writer));
+        self.apu.save_state(&mut writer);
+        self.serial.save_state(&mut writer);
+
+        writer.finish()
+    }
+
+    /// Restores a snapshot made with `save_state`. If the snapshot can't be loaded the
+    /// GameBoy is left as it was
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        let backup = self.save_state();
+
+        self.load_state_unchecked(data).inspect_err(|_| {
+            self.load_state_unchecked(&backup)
+                .expect("a save state we just made should always be loadable");
+        })
+    }
+
+    fn load_state_unchecked(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        let mut reader = StateReader::new(data, self.rom_checksum())?;
+
+        self.cpu.load_state(&mut reader)?;
+        self.timer.load_state(&mut reader)?;
+        self.registers.load_state(&mut reader)?;
+        self.flags.load_state(&mut reader)?;
+        self.bus.load_state(&mut reader)?;
+        self.gpu.load_state(&mut reader)?;
+        for layer in self.layers.iter_mut() {
+            layer.load_state(&mut reader)?;
+        }
+        self.apu.load_state(&mut reader)?;
+        self.serial.load_state(&mut reader)?;
+
+        reader.finish()
+    }
+
+    /// The global checksum in the rom header, used to check that a save state belongs to
+    /// this game
+    fn rom_checksum(&self) -> u16 {
+        self.header.global_checksum
     }
 
     /// Calls the `step` function repeatedly and exists when a frame has finished
-    /// rendering
+    /// rendering. When the GameBoy is stopped the LCD is off, so this returns right away
+    /// to let the frontend update the joypad
     pub fn step_for_a_frame(&mut self) {
-        // Waiting for VBlank
-        while self.gpu.state != GpuState::VBlank {
-            self.step();
-        }
+        let frame_count = self.gpu.frame_count;
 
-        // ... and then we wait for OAM Search, otherwise it gets stuck in VBlank
-        while self.gpu.state != GpuState::OamSearch {
+        // Frames also end when the LCD is turned off, nothing is drawn but the time
+        // still passes
+        while self.gpu.frame_count == frame_count {
             self.step();
+
+            if self.cpu.stopped {
+                return;
+            }
         }
     }
 }
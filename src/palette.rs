@@ -0,0 +1,134 @@
+//! The original GameBoy only has 4 shades, the colors they are shown with are up to the
+//! frontend. A `Palette` has the colors for the background and for the two sprite
+//! palettes, like the GameBoy Color does when it runs an old game, which is why `Gpu`
+//! remembers which palette register every pixel of the screen came from.
+//!
+//! The GameBoy Color palettes are from here: https://gbdev.io/pandocs/Power_Up_Sequence.html#compatibility-palettes
+
+use crate::gpu::{Color, PaletteRegister};
+
+/// A color with 8 bits per channel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    /// Takes a color written like in CSS, `0xRRGGBB`
+    pub const fn from_hex(hex: u32) -> Self {
+        Self {
+            r: (hex >> 16) as u8,
+            g: (hex >> 8) as u8,
+            b: hex as u8,
+        }
+    }
+
+    /// The format used by the GameBoy Color and the Super GameBoy, 5 bits per channel with
+    /// red in the lowest bits
+    pub const fn from_rgb555(color: u16) -> Self {
+        Self {
+            r: expand_5_bits((color & 0x1F) as u8),
+            g: expand_5_bits(((color >> 5) & 0x1F) as u8),
+            b: expand_5_bits(((color >> 10) & 0x1F) as u8),
+        }
+    }
+
+    pub const fn to_rgba8(self) -> [u8; 4] {
+        [self.r, self.g, self.b, 0xFF]
+    }
+
+    /// 5 bits of red in the highest bits, 6 of green and 5 of blue
+    pub const fn to_rgb565(self) -> u16 {
+        (self.r as u16 >> 3) << 11 | (self.g as u16 >> 2) << 5 | self.b as u16 >> 3
+    }
+}
+
+/// The colors of the 4 shades, from `Color::Light` to `Color::Dark`, for each palette
+/// register
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub background: [Rgb; 4],
+    pub object_0: [Rgb; 4],
+    pub object_1: [Rgb; 4],
+}
+
+impl Palette {
+    /// A palette that uses the same colors for everything, like the original GameBoy
+    pub const fn new(colors: [Rgb; 4]) -> Self {
+        Self {
+            background: colors,
+            object_0: colors,
+            object_1: colors,
+        }
+    }
+
+    /// The green screen of the original GameBoy
+    pub const CLASSIC_GREEN: Self = Self::new(hex_colors([0xE8FCCC, 0xACD490, 0x548C70, 0x142C38]));
+
+    /// The grey screen of the GameBoy Pocket
+    pub const POCKET_GREY: Self = Self::new(hex_colors([0xE0DBCD, 0xA89F94, 0x706B66, 0x2B2B26]));
+
+    // The palettes the GameBoy Color boot rom lets you pick with the directional pad and
+    // the buttons, the names are the button combinations
+
+    pub const CGB_UP: Self = Self::new(hex_colors([0xFFFFFF, 0xFFAD63, 0x843100, 0x000000]));
+    pub const CGB_UP_A: Self = Self {
+        background: hex_colors([0xFFFFFF, 0xFF8484, 0x943A3A, 0x000000]),
+        object_0: hex_colors([0xFFFFFF, 0x7BFF31, 0x008400, 0x000000]),
+        object_1: hex_colors([0xFFFFFF, 0x63A5FF, 0x0000FF, 0x000000]),
+    };
+    pub const CGB_UP_B: Self = Self::new(hex_colors([0xFFE6C5, 0xCE9C84, 0x846B29, 0x5A3108]));
+    pub const CGB_LEFT: Self = Self {
+        background: hex_colors([0xFFFFFF, 0x63A5FF, 0x0000FF, 0x000000]),
+        object_0: hex_colors([0xFFFFFF, 0xFF8484, 0x943A3A, 0x000000]),
+        object_1: hex_colors([0xFFFFFF, 0x7BFF31, 0x008400, 0x000000]),
+    };
+    pub const CGB_LEFT_A: Self = Self {
+        background: hex_colors([0xFFFFFF, 0x8C8CDE, 0x52528C, 0x000000]),
+        object_0: hex_colors([0xFFFFFF, 0xFF8484, 0x943A3A, 0x000000]),
+        object_1: hex_colors([0xFFFFFF, 0xFFAD63, 0x843100, 0x000000]),
+    };
+    pub const CGB_LEFT_B: Self = Self::new(hex_colors([0xFFFFFF, 0xA5A5A5, 0x525252, 0x000000]));
+    pub const CGB_DOWN: Self = Self::new(hex_colors([0xFFFFA5, 0xFF9494, 0x9494FF, 0x000000]));
+    pub const CGB_DOWN_A: Self = Self::new(hex_colors([0xFFFFFF, 0xFFFF00, 0xFF0000, 0x000000]));
+    pub const CGB_DOWN_B: Self = Self {
+        background: hex_colors([0xFFFFFF, 0xFFFF00, 0x7B4A00, 0x000000]),
+        object_0: hex_colors([0xFFFFFF, 0x63A5FF, 0x0000FF, 0x000000]),
+        object_1: hex_colors([0xFFFFFF, 0x7BFF31, 0x008400, 0x000000]),
+    };
+    pub const CGB_RIGHT: Self = Self::new(hex_colors([0xFFFFFF, 0x52FF00, 0xFF4200, 0x000000]));
+
+    /// This is also the palette of the games the boot rom doesn't know
+    pub const CGB_RIGHT_A: Self = Self {
+        background: hex_colors([0xFFFFFF, 0x7BFF31, 0x0063C5, 0x000000]),
+        object_0: hex_colors([0xFFFFFF, 0xFF8484, 0x943A3A, 0x000000]),
+        object_1: hex_colors([0xFFFFFF, 0xFF8484, 0x943A3A, 0x000000]),
+    };
+    pub const CGB_RIGHT_B: Self = Self::new(hex_colors([0x000000, 0x008484, 0xFFDE00, 0xFFFFFF]));
+
+    pub fn get_color(&self, color: Color, register: PaletteRegister) -> Rgb {
+        let colors = match register {
+            PaletteRegister::Bgp => &self.background,
+            PaletteRegister::Obp0 => &self.object_0,
+            PaletteRegister::Obp1 => &self.object_1,
+        };
+
+        colors[color as usize]
+    }
+}
+
+const fn hex_colors(hex: [u32; 4]) -> [Rgb; 4] {
+    [
+        Rgb::from_hex(hex[0]),
+        Rgb::from_hex(hex[1]),
+        Rgb::from_hex(hex[2]),
+        Rgb::from_hex(hex[3]),
+    ]
+}
+
+/// Copies the highest bits in the lowest ones, so that 0x1F becomes 0xFF
+const fn expand_5_bits(value: u8) -> u8 {
+    value << 3 | value >> 2
+}
@@ -1,7 +1,8 @@
 use crate::{
-    bus::Bus,
     common::{merge_two_u8s_into_u16, split_u16_into_two_u8s},
+    cpu::cpu_bus::CpuBus,
     flags::Flags,
+    save_state::{SaveState, SaveStateError, StateReader, StateWriter},
 };
 
 #[derive(PartialEq, Eq)]
@@ -32,7 +33,39 @@ impl Registers {
         }
     }
 
-    pub(crate) fn get_register(&self, code: u8, bus: &Bus) -> u8 {
+    /// The GameBoy Color boot rom leaves different values behind, games check A to know
+    /// whether or not they are running on a GameBoy Color
+    pub(crate) fn new_cgb() -> Self {
+        Self {
+            a: 0x11,
+            b: 0x00,
+            c: 0x00,
+            d: 0xFF,
+            e: 0x56,
+            h: 0x00,
+            l: 0x0D,
+            sp: 0xFFFE,
+            pc: 0x0100,
+        }
+    }
+
+    /// Everything starts at 0 when the GameBoy is turned on, the values in `new` are the
+    /// ones the boot rom leaves behind
+    pub(crate) fn new_before_boot() -> Self {
+        Self {
+            a: 0,
+            b: 0,
+            c: 0,
+            d: 0,
+            e: 0,
+            h: 0,
+            l: 0,
+            sp: 0,
+            pc: 0,
+        }
+    }
+
+    pub(crate) fn get_register<B: CpuBus>(&self, code: u8, bus: &mut B) -> u8 {
         match code & 0b00000111 {
             0 => self.b,
             1 => self.c,
@@ -47,7 +80,7 @@ impl Registers {
         }
     }
 
-    pub(crate) fn set_register(&mut self, code: u8, value: u8, bus: &mut Bus) {
+    pub(crate) fn set_register<B: CpuBus>(&mut self, code: u8, value: u8, bus: &mut B) {
         match code & 0b00000111 {
             0 => self.b = value,
             1 => self.c = value,
@@ -142,6 +175,36 @@ impl Registers {
     }
 }
 
+impl SaveState for Registers {
+    fn save_state(&self, writer: &mut StateWriter) {
+        for register in [self.a, self.b, self.c, self.d, self.e, self.h, self.l] {
+            writer.write_u8(register);
+        }
+
+        writer.write_u16(self.sp);
+        writer.write_u16(self.pc);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        for register in [
+            &mut self.a,
+            &mut self.b,
+            &mut self.c,
+            &mut self.d,
+            &mut self.e,
+            &mut self.h,
+            &mut self.l,
+        ] {
+            *register = reader.read_u8()?;
+        }
+
+        self.sp = reader.read_u16()?;
+        self.pc = reader.read_u16()?;
+
+        Ok(())
+    }
+}
+
 impl Default for Registers {
     fn default() -> Self {
         Self::new()
@@ -0,0 +1,386 @@
+//! Some MBC3 cartridges have a real time clock that keeps counting even when the GameBoy
+//! is off, we emulate this by looking at how much time has passed in the real world
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    common::Bit,
+    save_state::{SaveState, SaveStateError, StateReader, StateWriter},
+};
+
+/// The size of the clock data other emulators put at the end of `.sav` files
+pub(crate) const RTC_SAVE_DATA_SIZE: usize = 48;
+
+/// Where the clock gets the current time from, by default it's the system clock but it
+/// can be replaced, for example to make tests deterministic
+pub trait TimeSource: Send {
+    /// The number of seconds since the unix epoch
+    fn now(&self) -> u64;
+}
+
+pub struct SystemTimeSource;
+
+impl TimeSource for SystemTimeSource {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+/// The clock registers, in the same order as the ram bank numbers that select them
+#[derive(Clone, Copy, Default)]
+struct RtcRegisters {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    days_low: u8,
+
+    /// - Bit 0: Bit 8 of the day counter
+    /// - Bit 6: Halt, the clock stops counting when set
+    /// - Bit 7: Day counter carry, gets set when the day counter overflows
+    days_high: u8,
+}
+
+impl RtcRegisters {
+    fn get(&self, register: u8) -> u8 {
+        match register {
+            0x08 => self.seconds,
+            0x09 => self.minutes,
+            0x0A => self.hours,
+            0x0B => self.days_low,
+            _ => self.days_high,
+        }
+    }
+
+    fn set(&mut self, register: u8, value: u8) {
+        match register {
+            0x08 => self.seconds = value & 0b00111111,
+            0x09 => self.minutes = value & 0b00111111,
+            0x0A => self.hours = value & 0b00011111,
+            0x0B => self.days_low = value,
+            _ => self.days_high = value & 0b11000001,
+        }
+    }
+
+    fn days(&self) -> u64 {
+        ((self.days_high as u64 & 0b1) << 8) | self.days_low as u64
+    }
+
+    fn advance(&mut self, seconds: u64) {
+        let seconds = self.seconds as u64 + seconds;
+        let minutes = self.minutes as u64 + seconds / 60;
+        let hours = self.hours as u64 + minutes / 60;
+        let days = self.days() + hours / 24;
+
+        self.seconds = (seconds % 60) as u8;
+        self.minutes = (minutes % 60) as u8;
+        self.hours = (hours % 24) as u8;
+        self.days_low = days as u8;
+        self.days_high.set_bit(0, days & 0x100 != 0);
+
+        // The carry stays set until the game clears it
+        if days > 0x1FF {
+            self.days_high.set_bit(7, true);
+        }
+    }
+
+    fn write_save_data(&self, data: &mut Vec<u8>) {
+        for register in [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.days_low,
+            self.days_high,
+        ] {
+            data.extend_from_slice(&(register as u32).to_le_bytes());
+        }
+    }
+
+    fn read_save_data(data: &[u8]) -> Self {
+        let register = |i: usize| data[i * 4];
+        let mut registers = Self::default();
+
+        for i in 0..5 {
+            registers.set(0x08 + i as u8, register(i));
+        }
+
+        registers
+    }
+}
+
+pub(crate) struct Rtc {
+    time_source: Box<dyn TimeSource>,
+    registers: RtcRegisters,
+
+    /// The game can't read the clock directly, it has to latch it first, which copies the
+    /// registers here
+    latched_registers: RtcRegisters,
+
+    /// Latching is done by writing 0 and then 1, this is true after writing 0
+    is_latch_ready: bool,
+
+    /// The time at which `registers` were last brought up to date
+    last_update: u64,
+}
+
+impl Rtc {
+    pub(crate) fn new() -> Self {
+        let time_source = Box::new(SystemTimeSource);
+
+        Self {
+            last_update: time_source.now(),
+            time_source,
+            registers: RtcRegisters::default(),
+            latched_registers: RtcRegisters::default(),
+            is_latch_ready: false,
+        }
+    }
+
+    pub(crate) fn set_time_source(&mut self, time_source: Box<dyn TimeSource>) {
+        self.update();
+        self.last_update = time_source.now();
+        self.time_source = time_source;
+    }
+
+    /// Adds the time that has passed since the last update to the registers
+    fn update(&mut self) {
+        (self.registers, self.last_update) = self.get_current_registers();
+    }
+
+    /// Returns what the registers should be right now, and the time they refer to
+    fn get_current_registers(&self) -> (RtcRegisters, u64) {
+        let now = self.time_source.now();
+        let mut registers = self.registers;
+
+        if !registers.days_high.get_bit(6) {
+            registers.advance(now.saturating_sub(self.last_update));
+        }
+
+        (registers, now)
+    }
+
+    /// `register` is the ram bank number, from 0x08 to 0x0C
+    pub(crate) fn read(&self, register: u8) -> u8 {
+        self.latched_registers.get(register)
+    }
+
+    /// Writing to the registers sets the clock directly
+    pub(crate) fn write(&mut self, register: u8, value: u8) {
+        self.update();
+        self.registers.set(register, value);
+        self.latched_registers.set(register, value);
+    }
+
+    /// Called when the game writes to 6000-7FFF
+    pub(crate) fn signal_latch_write(&mut self, value: u8) {
+        if value == 1 && self.is_latch_ready {
+            self.update();
+            self.latched_registers = self.registers;
+        }
+
+        self.is_latch_ready = value == 0;
+    }
+
+    /// The format used by most emulators, 5 registers and then 5 latched registers, each
+    /// one as a 32 bit number, and lastly a 64 bit unix timestamp
+    pub(crate) fn get_save_data(&self) -> Vec<u8> {
+        let (registers, now) = self.get_current_registers();
+
+        let mut data = Vec::with_capacity(RTC_SAVE_DATA_SIZE);
+        registers.write_save_data(&mut data);
+        self.latched_registers.write_save_data(&mut data);
+        data.extend_from_slice(&now.to_le_bytes());
+
+        data
+    }
+
+    /// Some emulators only store 32 bits of the timestamp, so this works with 44 bytes as
+    /// well. The time that has passed since the game was saved gets added to the clock
+    pub(crate) fn load_save_data(&mut self, data: &[u8]) {
+        if data.len() < RTC_SAVE_DATA_SIZE - 4 {
+            return;
+        }
+
+        self.registers = RtcRegisters::read_save_data(&data[0..20]);
+        self.latched_registers = RtcRegisters::read_save_data(&data[20..40]);
+
+        let mut timestamp = [0u8; 8];
+        let timestamp_size = data.len().min(RTC_SAVE_DATA_SIZE) - 40;
+        timestamp[..timestamp_size].copy_from_slice(&data[40..40 + timestamp_size]);
+        self.last_update = u64::from_le_bytes(timestamp);
+
+        self.update();
+    }
+}
+
+impl SaveState for RtcRegisters {
+    fn save_state(&self, writer: &mut StateWriter) {
+        for register in 0x08..=0x0C {
+            writer.write_u8(self.get(register));
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        for register in 0x08..=0x0C {
+            self.set(register, reader.read_u8()?);
+        }
+
+        Ok(())
+    }
+}
+
+/// The time source is not saved, and the time that passed between saving and loading is
+/// added to the clock, like when loading a `.sav` file
+impl SaveState for Rtc {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.registers.save_state(writer);
+        self.latched_registers.save_state(writer);
+        writer.write_bool(self.is_latch_ready);
+        writer.write_u64(self.last_update);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.registers.load_state(reader)?;
+        self.latched_registers.load_state(reader)?;
+        self.is_latch_ready = reader.read_bool()?;
+        self.last_update = reader.read_u64()?;
+        self.update();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    /// A clock that only moves when the test says so
+    #[derive(Clone, Default)]
+    struct FakeTimeSource(Arc<AtomicU64>);
+
+    impl FakeTimeSource {
+        fn advance(&self, seconds: u64) {
+            self.0.fetch_add(seconds, Ordering::SeqCst);
+        }
+    }
+
+    impl TimeSource for FakeTimeSource {
+        fn now(&self) -> u64 {
+            self.0.load(Ordering::SeqCst)
+        }
+    }
+
+    fn new_rtc() -> (Rtc, FakeTimeSource) {
+        let time_source = FakeTimeSource::default();
+        let mut rtc = Rtc::new();
+        rtc.set_time_source(Box::new(time_source.clone()));
+
+        (rtc, time_source)
+    }
+
+    fn latch(rtc: &mut Rtc) {
+        rtc.signal_latch_write(0);
+        rtc.signal_latch_write(1);
+    }
+
+    #[test]
+    fn latching_copies_the_current_time() {
+        let (mut rtc, time_source) = new_rtc();
+        time_source.advance(90);
+
+        // Nothing changes until the clock is latched
+        assert_eq!(rtc.read(0x08), 0);
+
+        // Writing 1 without writing 0 first doesn't latch
+        rtc.signal_latch_write(1);
+        assert_eq!(rtc.read(0x08), 0);
+
+        latch(&mut rtc);
+        assert_eq!(rtc.read(0x08), 30);
+        assert_eq!(rtc.read(0x09), 1);
+
+        // The latched registers stay the same while the clock keeps going
+        time_source.advance(10);
+        assert_eq!(rtc.read(0x08), 30);
+
+        latch(&mut rtc);
+        assert_eq!(rtc.read(0x08), 40);
+    }
+
+    #[test]
+    fn halt_stops_the_clock() {
+        let (mut rtc, time_source) = new_rtc();
+        rtc.write(0x0C, 0b01000000);
+        time_source.advance(100);
+
+        latch(&mut rtc);
+        assert_eq!(rtc.read(0x08), 0);
+        assert_eq!(rtc.read(0x0C), 0b01000000);
+
+        rtc.write(0x0C, 0);
+        time_source.advance(5);
+
+        latch(&mut rtc);
+        assert_eq!(rtc.read(0x08), 5);
+    }
+
+    #[test]
+    fn day_counter_carry_is_set_after_day_511() {
+        let (mut rtc, time_source) = new_rtc();
+        rtc.write(0x08, 59);
+        rtc.write(0x09, 59);
+        rtc.write(0x0A, 23);
+        rtc.write(0x0B, 0xFF);
+        rtc.write(0x0C, 0b00000001);
+
+        time_source.advance(1);
+        latch(&mut rtc);
+
+        assert_eq!(rtc.read(0x08), 0);
+        assert_eq!(rtc.read(0x09), 0);
+        assert_eq!(rtc.read(0x0A), 0);
+        assert_eq!(rtc.read(0x0B), 0);
+        assert_eq!(rtc.read(0x0C), 0b10000000);
+
+        // The carry stays set until the game clears it
+        time_source.advance(60 * 60 * 24);
+        latch(&mut rtc);
+        assert_eq!(rtc.read(0x0B), 1);
+        assert_eq!(rtc.read(0x0C), 0b10000000);
+    }
+
+    #[test]
+    fn save_data_round_trip() {
+        let (mut rtc, time_source) = new_rtc();
+        time_source.advance(3 * 60 * 60 + 2 * 60 + 1);
+        latch(&mut rtc);
+        time_source.advance(1);
+
+        let data = rtc.get_save_data();
+        assert_eq!(data.len(), RTC_SAVE_DATA_SIZE);
+
+        let mut loaded_rtc = Rtc::new();
+        loaded_rtc.set_time_source(Box::new(time_source.clone()));
+        loaded_rtc.load_save_data(&data);
+
+        // The latched registers are restored as they were
+        assert_eq!(loaded_rtc.read(0x08), 1);
+        assert_eq!(loaded_rtc.read(0x09), 2);
+        assert_eq!(loaded_rtc.read(0x0A), 3);
+
+        // The time that passed while the game was off is added to the clock
+        time_source.advance(10);
+        loaded_rtc.load_save_data(&data);
+        latch(&mut loaded_rtc);
+        assert_eq!(loaded_rtc.read(0x08), 12);
+        assert_eq!(loaded_rtc.read(0x09), 2);
+        assert_eq!(loaded_rtc.read(0x0A), 3);
+    }
+}
@@ -0,0 +1,211 @@
+//! A save state is a snapshot of the whole GameBoy, it's a simple binary format made of a
+//! header and then every piece of hardware writing its own state one after the other
+
+use std::{error::Error, fmt::Display};
+
+/// Every save state starts with this, so we can tell if we are given something else
+const MAGIC: &[u8; 4] = b"GMSS";
+
+/// This has to be incremented every time the save state layout changes, old save states
+/// can't be loaded because the data would be misplaced
+pub const SAVE_STATE_VERSION: u16 = 1;
+
+/// Implemented by every piece of hardware that has some state, loading must read exactly
+/// what saving wrote, in the same order
+pub trait SaveState {
+    fn save_state(&self, writer: &mut StateWriter);
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError>;
+}
+
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    /// `rom_checksum` identifies the game, so the state does not get loaded in another one
+    pub(crate) fn new(rom_checksum: u16) -> Self {
+        let mut writer = Self { data: Vec::new() };
+
+        writer.write_bytes(MAGIC);
+        writer.write_u16(SAVE_STATE_VERSION);
+        writer.write_u16(rom_checksum);
+
+        writer
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub(crate) fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub(crate) fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub(crate) fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn write_f32(&mut self, value: f32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes bytes whose length is always the same, like the ram arrays
+    pub(crate) fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    /// Writes bytes whose length can change, the length is written first
+    pub(crate) fn write_vec(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.write_bytes(bytes);
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    /// Checks the header and returns a reader that's right after it
+    pub(crate) fn new(data: &'a [u8], rom_checksum: u16) -> Result<Self, SaveStateError> {
+        let mut reader = Self { data, position: 0 };
+
+        let mut magic = [0u8; 4];
+        reader
+            .read_bytes(&mut magic)
+            .map_err(|_| SaveStateError::NotASaveState)?;
+
+        if &magic != MAGIC {
+            return Err(SaveStateError::NotASaveState);
+        }
+
+        let version = reader.read_u16()?;
+        if version != SAVE_STATE_VERSION {
+            return Err(SaveStateError::IncompatibleVersion {
+                expected: SAVE_STATE_VERSION,
+                found: version,
+            });
+        }
+
+        if reader.read_u16()? != rom_checksum {
+            return Err(SaveStateError::DifferentRom);
+        }
+
+        Ok(reader)
+    }
+
+    /// Every byte should have been read by the end, otherwise the state is wrong
+    pub(crate) fn finish(self) -> Result<(), SaveStateError> {
+        match self.position == self.data.len() {
+            true => Ok(()),
+            false => Err(SaveStateError::Corrupted),
+        }
+    }
+
+    fn take(&mut self, amount: usize) -> Result<&'a [u8], SaveStateError> {
+        let end = self.position + amount;
+        let bytes = self
+            .data
+            .get(self.position..end)
+            .ok_or(SaveStateError::Corrupted)?;
+
+        self.position = end;
+        Ok(bytes)
+    }
+
+    pub(crate) fn read_u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn read_bool(&mut self) -> Result<bool, SaveStateError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SaveStateError::Corrupted),
+        }
+    }
+
+    pub(crate) fn read_u16(&mut self) -> Result<u16, SaveStateError> {
+        let mut bytes = [0u8; 2];
+        self.read_bytes(&mut bytes)?;
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn read_u32(&mut self) -> Result<u32, SaveStateError> {
+        let mut bytes = [0u8; 4];
+        self.read_bytes(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn read_u64(&mut self) -> Result<u64, SaveStateError> {
+        let mut bytes = [0u8; 8];
+        self.read_bytes(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn read_f32(&mut self) -> Result<f32, SaveStateError> {
+        let mut bytes = [0u8; 4];
+        self.read_bytes(&mut bytes)?;
+        Ok(f32::from_le_bytes(bytes))
+    }
+
+    /// Fills `bytes` completely, the counterpart of `StateWriter::write_bytes`
+    pub(crate) fn read_bytes(&mut self, bytes: &mut [u8]) -> Result<(), SaveStateError> {
+        bytes.copy_from_slice(self.take(bytes.len())?);
+        Ok(())
+    }
+
+    /// The counterpart of `StateWriter::write_vec`, the length has to match the one of
+    /// `bytes`, because the sizes of things like the external ram never change
+    pub(crate) fn read_vec(&mut self, bytes: &mut [u8]) -> Result<(), SaveStateError> {
+        if self.read_u32()? as usize != bytes.len() {
+            return Err(SaveStateError::Corrupted);
+        }
+
+        self.read_bytes(bytes)
+    }
+}
+
+#[derive(Debug)]
+pub enum SaveStateError {
+    /// The data does not start with the save state header
+    NotASaveState,
+
+    /// The save state was made by a version of the emulator with a different layout
+    IncompatibleVersion { expected: u16, found: u16 },
+
+    /// The save state was made while playing another game
+    DifferentRom,
+
+    /// The data ends too early, or contains values that make no sense
+    Corrupted,
+}
+
+impl Error for SaveStateError {}
+impl Display for SaveStateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotASaveState => write!(f, "data is not a save state"),
+            Self::IncompatibleVersion { expected, found } => write!(
+                f,
+                "save state has version {} but only version {} is supported",
+                found, expected
+            ),
+            Self::DifferentRom => write!(f, "save state was made with another rom"),
+            Self::Corrupted => write!(f, "save state is corrupted"),
+        }
+    }
+}
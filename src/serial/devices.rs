@@ -0,0 +1,104 @@
+use std::sync::{Arc, Mutex};
+
+use super::SerialDevice;
+
+/// Nothing plugged in, the line stays high so every byte received is 0xFF, and since
+/// nothing drives the clock a transfer with the external clock never ends
+pub struct NullSerialDevice;
+
+impl SerialDevice for NullSerialDevice {
+    fn exchange_bit(&mut self, _bit: bool) -> bool {
+        true
+    }
+}
+
+/// Records every byte the GameBoy sends, test roms use this to print their results.
+/// Clones share the same log, so keep one around to read it after plugging the other in
+#[derive(Clone, Default)]
+pub struct SerialLogger {
+    bytes: Arc<Mutex<Vec<u8>>>,
+    current_byte: u8,
+    received_bits: u8,
+}
+
+impl SerialLogger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every complete byte sent so far
+    pub fn bytes(&self) -> Vec<u8> {
+        self.bytes.lock().unwrap().clone()
+    }
+}
+
+impl SerialDevice for SerialLogger {
+    fn exchange_bit(&mut self, bit: bool) -> bool {
+        self.current_byte = (self.current_byte << 1) | bit as u8;
+        self.received_bits += 1;
+
+        if self.received_bits == 8 {
+            self.bytes.lock().unwrap().push(self.current_byte);
+            self.current_byte = 0;
+            self.received_bits = 0;
+        }
+
+        true
+    }
+}
+
+/// What one end of the cable sees
+struct CableEnd {
+    /// The bit the GameBoy on this end would shift out
+    bit_out: bool,
+
+    /// The bit sent by the other end with its last clock pulse, if this end hasn't seen
+    /// it yet. A real cable doesn't buffer anything, so a newer pulse replaces it
+    bit_in: Option<bool>,
+}
+
+/// One end of a link cable between two GameBoys in the same process, see `link_cable`
+pub struct LinkCablePort {
+    ends: Arc<Mutex<[CableEnd; 2]>>,
+    side: usize,
+}
+
+/// Creates a link cable, each port has to be plugged in a different GameBoy. The two
+/// GameBoys should be stepped in lockstep (for example one `step` each), so the one that
+/// waits for the clock sees the bits as soon as they are sent
+pub fn link_cable() -> (LinkCablePort, LinkCablePort) {
+    let new_end = || CableEnd {
+        bit_out: true,
+        bit_in: None,
+    };
+    let ends = Arc::new(Mutex::new([new_end(), new_end()]));
+
+    (
+        LinkCablePort {
+            ends: ends.clone(),
+            side: 0,
+        },
+        LinkCablePort { ends, side: 1 },
+    )
+}
+
+impl SerialDevice for LinkCablePort {
+    fn exchange_bit(&mut self, bit: bool) -> bool {
+        let mut ends = self.ends.lock().unwrap();
+
+        // This end drives its own clock, so it's not listening for the other one
+        ends[self.side].bit_in = None;
+
+        let other_end = &mut ends[1 - self.side];
+        other_end.bit_in = Some(bit);
+        other_end.bit_out
+    }
+
+    fn poll_external_clock(&mut self, bit: bool) -> Option<bool> {
+        let mut ends = self.ends.lock().unwrap();
+        let end = &mut ends[self.side];
+
+        end.bit_out = bit;
+        end.bit_in.take()
+    }
+}
@@ -0,0 +1,138 @@
+//! The serial port is how two GameBoys talk through the link cable. A transfer shifts the
+//! 8 bits of SB out one at a time, starting from bit 7, and at the same time shifts in
+//! the bits sent by the other side. One of the two GameBoys drives the clock, the other
+//! one waits for it.
+//!
+//! This is a really good resource: https://gbdev.io/pandocs/Serial_Data_Transfer_(Link_Cable).html
+
+mod devices;
+
+pub use devices::{link_cable, LinkCablePort, NullSerialDevice, SerialLogger};
+
+use crate::{
+    bus::Bus,
+    common::Bit,
+    consts::{
+        bus::IO_START,
+        cpu::IF,
+        serial::{SB, SC},
+    },
+    save_state::{SaveState, SaveStateError, StateReader, StateWriter},
+};
+
+/// The internal clock runs at 8192hz, so a bit is shifted every 512 dots
+const INTERNAL_CLOCK_TICKS: u16 = 512;
+
+/// Whatever is plugged in the other end of the link cable
+pub trait SerialDevice: Send {
+    /// Called when the GameBoy drives the clock, `bit` is the one it shifts out and the
+    /// return value is the one the device shifts back in
+    fn exchange_bit(&mut self, bit: bool) -> bool;
+
+    /// Called every cycle when the GameBoy waits for the device to drive the clock, `bit`
+    /// is the one the GameBoy would shift out. Returns the bit the device sends if it
+    /// drove the clock, `None` otherwise
+    #[allow(unused_variables)]
+    fn poll_external_clock(&mut self, bit: bool) -> Option<bool> {
+        None
+    }
+}
+
+pub struct Serial {
+    device: Box<dyn SerialDevice>,
+
+    /// Counts dots until the next bit is shifted when using the internal clock
+    clock_ticks: u16,
+
+    /// How many bits of the current transfer have been shifted
+    shifted_bits: u8,
+}
+
+impl Serial {
+    pub(crate) fn new() -> Self {
+        Self {
+            device: Box::new(NullSerialDevice),
+            clock_ticks: 0,
+            shifted_bits: 0,
+        }
+    }
+
+    /// Plugs a device in the other end of the link cable, replacing the previous one
+    pub fn set_device(&mut self, device: Box<dyn SerialDevice>) {
+        self.device = device;
+    }
+}
+
+impl Serial {
+    /// This needs to be called once every cycle
+    pub(crate) fn tick(&mut self, bus: &mut Bus) {
+        // Writing to SC starts a new transfer from scratch
+        if bus.needs_to_start_serial_transfer {
+            self.clock_ticks = 0;
+            self.shifted_bits = 0;
+            bus.needs_to_start_serial_transfer = false;
+        }
+
+        let sc = bus.read(SC);
+        let is_transferring = sc.get_bit(7);
+        let bit_out = bus.read(SB).get_bit(7);
+
+        if sc.get_bit(0) {
+            if !is_transferring {
+                return;
+            }
+
+            self.clock_ticks += 4;
+            if self.clock_ticks < INTERNAL_CLOCK_TICKS {
+                return;
+            }
+
+            self.clock_ticks = 0;
+            let bit_in = self.device.exchange_bit(bit_out);
+            self.shift(bus, bit_in);
+        } else {
+            // The device can drive the clock even when we are not transferring, in that
+            // case the bit just gets lost
+            let Some(bit_in) = self.device.poll_external_clock(bit_out) else {
+                return;
+            };
+
+            if is_transferring {
+                self.shift(bus, bit_in);
+            }
+        }
+    }
+
+    /// Shifts `bit_in` in SB, once all 8 bits are shifted the transfer ends and the serial
+    /// interrupt is requested
+    fn shift(&mut self, bus: &mut Bus, bit_in: bool) {
+        bus.write(SB, (bus.read(SB) << 1) | bit_in as u8);
+        self.shifted_bits += 1;
+
+        if self.shifted_bits == 8 {
+            self.shifted_bits = 0;
+
+            // SC is written directly, so it doesn't look like the game started a transfer
+            let mut sc = bus.read(SC);
+            sc.set_bit(7, false);
+            bus.io[(SC - IO_START as u16) as usize] = sc;
+
+            bus.write(IF, bus.read(IF) | 0b00001000); // Enabling serial interrupt
+        }
+    }
+}
+
+/// The device is not saved, it's up to the frontend to plug it back in
+impl SaveState for Serial {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.clock_ticks);
+        writer.write_u8(self.shifted_bits);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.clock_ticks = reader.read_u16()?;
+        self.shifted_bits = reader.read_u8()?;
+
+        Ok(())
+    }
+}
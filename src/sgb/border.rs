@@ -0,0 +1,107 @@
+use crate::{
+    common::{merge_two_u8s_into_u16, Bit},
+    consts::{
+        display::{DISPLAY_SIZE_X, DISPLAY_SIZE_Y},
+        sgb::{SGB_FRAME_SIZE_X, SGB_FRAME_SIZE_Y},
+    },
+    gpu::Color,
+};
+
+use super::{Mask, Sgb, Transfer, BORDER_MAP_SIZE, TRANSFER_SIZE};
+
+/// Where the screen of the game is in the frame, the border is all around it
+const SCREEN_START_X: usize = (SGB_FRAME_SIZE_X - DISPLAY_SIZE_X) / 2;
+const SCREEN_START_Y: usize = (SGB_FRAME_SIZE_Y - DISPLAY_SIZE_Y) / 2;
+
+impl Sgb {
+    /// Copies the data the game sent with `CHR_TRN` or `PCT_TRN`
+    pub(super) fn apply_transfer(&mut self, transfer: Transfer, data: &[u8; TRANSFER_SIZE]) {
+        match transfer {
+            Transfer::LowTiles => self.border_tiles[..TRANSFER_SIZE].copy_from_slice(data),
+            Transfer::HighTiles => self.border_tiles[TRANSFER_SIZE..].copy_from_slice(data),
+
+            // The palettes come right after the map
+            Transfer::Border => {
+                self.border_map.copy_from_slice(&data[..BORDER_MAP_SIZE]);
+
+                let palettes_end = BORDER_MAP_SIZE + self.border_palettes.len();
+                self.border_palettes
+                    .copy_from_slice(&data[BORDER_MAP_SIZE..palettes_end]);
+            }
+        }
+    }
+
+    /// The border is drawn above the screen of the game, which shows through the
+    /// transparent pixels of the border. The pixels around the screen that are
+    /// transparent show color 0
+    pub(super) fn draw_frame(&mut self, screen: &[[Color; DISPLAY_SIZE_X]; DISPLAY_SIZE_Y]) {
+        for y in 0..SGB_FRAME_SIZE_Y {
+            for x in 0..SGB_FRAME_SIZE_X {
+                if let Some(color) = self.get_border_color(x, y) {
+                    self.frame[y][x] = color;
+                    continue;
+                }
+
+                let screen_x = x.wrapping_sub(SCREEN_START_X);
+                let screen_y = y.wrapping_sub(SCREEN_START_Y);
+
+                if screen_x >= DISPLAY_SIZE_X || screen_y >= DISPLAY_SIZE_Y {
+                    self.frame[y][x] = self.palettes[0][0];
+                    continue;
+                }
+
+                self.frame[y][x] = match self.mask {
+                    Mask::Cancel => {
+                        let palette = self.attributes[screen_y / 8][screen_x / 8] as usize;
+                        self.palettes[palette][screen[screen_y][screen_x] as usize]
+                    }
+
+                    Mask::Freeze => self.frame[y][x],
+                    Mask::Black => 0x0000,
+                    Mask::Color0 => self.palettes[0][0],
+                }
+            }
+        }
+    }
+
+    /// The border is made of 8x8 tiles with 16 colors, like the ones of the SNES. Color
+    /// 0 is transparent, so it's `None`
+    fn get_border_color(&self, x: usize, y: usize) -> Option<u16> {
+        // Every tile in the map is 2 bytes, the lower 8 bits are the tile number
+        let map_index = (y / 8 * 32 + x / 8) * 2;
+        let entry =
+            merge_two_u8s_into_u16(self.border_map[map_index + 1], self.border_map[map_index]);
+
+        let tile = (entry & 0xFF) as usize;
+        let palette = ((entry >> 10) & 0b111) as usize;
+
+        let mut row = y % 8;
+        if entry.get_bit(15) {
+            row = 7 - row;
+        }
+
+        let mut bit = 7 - (x % 8) as u8;
+        if entry.get_bit(14) {
+            bit = 7 - bit;
+        }
+
+        // The rows are made of 4 bitplanes, the first two are in the first 16 bytes of
+        // the tile and the other two in the last 16
+        let address = tile * 32 + row * 2;
+        let color = (self.border_tiles[address].get_bit(bit) as usize)
+            | (self.border_tiles[address + 1].get_bit(bit) as usize) << 1
+            | (self.border_tiles[address + 16].get_bit(bit) as usize) << 2
+            | (self.border_tiles[address + 17].get_bit(bit) as usize) << 3;
+
+        if color == 0 {
+            return None;
+        }
+
+        // The border uses the palettes from 4 to 7
+        let index = ((palette.wrapping_sub(4) & 0b11) * 16 + color) * 2;
+        Some(
+            merge_two_u8s_into_u16(self.border_palettes[index + 1], self.border_palettes[index])
+                & 0x7FFF,
+        )
+    }
+}
@@ -0,0 +1,203 @@
+use crate::common::{merge_two_u8s_into_u16, Bit};
+
+use super::{Mask, Sgb, Transfer, ATTRIBUTES_X, ATTRIBUTES_Y};
+
+const PAL01: u8 = 0x00;
+const PAL23: u8 = 0x01;
+const PAL03: u8 = 0x02;
+const PAL12: u8 = 0x03;
+const ATTR_BLK: u8 = 0x04;
+const ATTR_LIN: u8 = 0x05;
+const ATTR_DIV: u8 = 0x06;
+const ATTR_CHR: u8 = 0x07;
+const MLT_REQ: u8 = 0x11;
+const CHR_TRN: u8 = 0x13;
+const PCT_TRN: u8 = 0x14;
+const MASK_EN: u8 = 0x17;
+
+impl Sgb {
+    /// `data` contains all the packets of the command, one after the other. The commands
+    /// that are not listed here are ignored
+    pub(super) fn execute_command(&mut self, data: &[u8]) {
+        match data[0] >> 3 {
+            PAL01 => self.set_palettes(0, 1, data),
+            PAL23 => self.set_palettes(2, 3, data),
+            PAL03 => self.set_palettes(0, 3, data),
+            PAL12 => self.set_palettes(1, 2, data),
+            ATTR_BLK => self.attr_blk(data),
+            ATTR_LIN => self.attr_lin(data),
+            ATTR_DIV => self.attr_div(data),
+            ATTR_CHR => self.attr_chr(data),
+            MLT_REQ => self.mlt_req(data),
+
+            CHR_TRN => {
+                self.pending_transfer = Some(match data[1].get_bit(0) {
+                    false => Transfer::LowTiles,
+                    true => Transfer::HighTiles,
+                })
+            }
+
+            PCT_TRN => self.pending_transfer = Some(Transfer::Border),
+
+            MASK_EN => {
+                self.mask = match data[1] & 0b11 {
+                    0 => Mask::Cancel,
+                    1 => Mask::Freeze,
+                    2 => Mask::Black,
+                    _ => Mask::Color0,
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    /// The first color is shared by all the palettes, then there are the other 3 colors
+    /// of both palettes
+    fn set_palettes(&mut self, first: usize, second: usize, data: &[u8]) {
+        let color_0 = read_color(data, 1);
+        for palette in self.palettes.iter_mut() {
+            palette[0] = color_0;
+        }
+
+        for i in 1..4 {
+            self.palettes[first][i] = read_color(data, 1 + i * 2);
+            self.palettes[second][i] = read_color(data, 7 + i * 2);
+        }
+    }
+
+    /// Colors rectangles, every one of them can change the cells inside it, on its border
+    /// and outside of it
+    fn attr_blk(&mut self, data: &[u8]) {
+        let data_sets = (data[1] & 0b00011111) as usize;
+
+        for data_set in data[2..].chunks_exact(6).take(data_sets) {
+            let control = data_set[0];
+            let inside_palette = data_set[1] & 0b11;
+            let outside_palette = (data_set[1] >> 4) & 0b11;
+            let (x1, y1) = (
+                (data_set[2] & 0b11111) as usize,
+                (data_set[3] & 0b11111) as usize,
+            );
+            let (x2, y2) = (
+                (data_set[4] & 0b11111) as usize,
+                (data_set[5] & 0b11111) as usize,
+            );
+
+            // When only the inside or only the outside is changed, the border changes
+            // with it
+            let border_palette = match control & 0b111 {
+                0b001 => Some(inside_palette),
+                0b100 => Some(outside_palette),
+                _ if control.get_bit(1) => Some((data_set[1] >> 2) & 0b11),
+                _ => None,
+            };
+
+            for (y, line) in self.attributes.iter_mut().enumerate() {
+                for (x, attribute) in line.iter_mut().enumerate() {
+                    let is_inside = x > x1 && x < x2 && y > y1 && y < y2;
+                    let is_on_border =
+                        !is_inside && (x1..=x2).contains(&x) && (y1..=y2).contains(&y);
+
+                    match (is_inside, is_on_border) {
+                        (true, _) if control.get_bit(0) => *attribute = inside_palette,
+                        (_, true) => *attribute = border_palette.unwrap_or(*attribute),
+                        (false, false) if control.get_bit(2) => *attribute = outside_palette,
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    /// Colors whole lines or columns, every byte is one of them
+    fn attr_lin(&mut self, data: &[u8]) {
+        let data_sets = data[1] as usize;
+
+        for &data_set in data[2..].iter().take(data_sets) {
+            let line = (data_set & 0b00011111) as usize;
+            let palette = (data_set >> 5) & 0b11;
+
+            match data_set.get_bit(7) {
+                true if line < ATTRIBUTES_Y => self.attributes[line].fill(palette),
+                false if line < ATTRIBUTES_X => self
+                    .attributes
+                    .iter_mut()
+                    .for_each(|row| row[line] = palette),
+                _ => {}
+            }
+        }
+    }
+
+    /// Splits the screen in two with a line, which has its own palette
+    fn attr_div(&mut self, data: &[u8]) {
+        let below_palette = data[1] & 0b11;
+        let above_palette = (data[1] >> 2) & 0b11;
+        let line_palette = (data[1] >> 4) & 0b11;
+        let is_horizontal = data[1].get_bit(6);
+        let coordinate = (data[2] & 0b00011111) as usize;
+
+        for (y, line) in self.attributes.iter_mut().enumerate() {
+            for (x, attribute) in line.iter_mut().enumerate() {
+                let position = match is_horizontal {
+                    true => y,
+                    false => x,
+                };
+
+                *attribute = match position.cmp(&coordinate) {
+                    std::cmp::Ordering::Less => above_palette,
+                    std::cmp::Ordering::Equal => line_palette,
+                    std::cmp::Ordering::Greater => below_palette,
+                };
+            }
+        }
+    }
+
+    /// Colors the cells one by one starting from a position, every byte has 4 of them
+    fn attr_chr(&mut self, data: &[u8]) {
+        let mut x = (data[1] as usize).min(ATTRIBUTES_X - 1);
+        let mut y = (data[2] as usize).min(ATTRIBUTES_Y - 1);
+        let cells = merge_two_u8s_into_u16(data[4], data[3]) as usize;
+        let is_vertical = data[5].get_bit(0);
+
+        let palettes = data[6..]
+            .iter()
+            .flat_map(|byte| [byte >> 6, byte >> 4, byte >> 2, *byte].map(|value| value & 0b11));
+
+        for palette in palettes.take(cells) {
+            self.attributes[y][x] = palette;
+
+            match is_vertical {
+                false => {
+                    x = (x + 1) % ATTRIBUTES_X;
+                    if x == 0 {
+                        y = (y + 1) % ATTRIBUTES_Y;
+                    }
+                }
+                true => {
+                    y = (y + 1) % ATTRIBUTES_Y;
+                    if y == 0 {
+                        x = (x + 1) % ATTRIBUTES_X;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Games use this to check if they are running on an SGB, the joypad id changes only
+    /// on the SGB
+    fn mlt_req(&mut self, data: &[u8]) {
+        self.players = match data[1] & 0b11 {
+            1 => 2,
+            3 => 4,
+            _ => 1,
+        };
+
+        self.current_player = 0;
+    }
+}
+
+/// Colors are 2 bytes in little endian
+fn read_color(data: &[u8], offset: usize) -> u16 {
+    merge_two_u8s_into_u16(data[offset + 1], data[offset]) & 0x7FFF
+}
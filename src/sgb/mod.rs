@@ -0,0 +1,384 @@
+//! The Super GameBoy is a cartridge for the SNES with a GameBoy inside. Games talk to it
+//! by sending packets through JOYP, one bit at a time, and it can color the screen with
+//! 4 palettes, draw a border around it and connect up to 4 joypads.
+//!
+//! A packet is 16 bytes long. It starts with a reset pulse (P14 and P15 low), then every
+//! bit is a pulse on P14 (a 0) or on P15 (a 1), with both lines going back high in
+//! between. After the 128 bits there's a 0 as a stop bit. The first byte of a command is
+//! the command number times 8 plus the number of packets the command is made of.
+//!
+//! This is a really good resource: https://gbdev.io/pandocs/SGB_Functions.html
+
+mod border;
+mod commands;
+
+use crate::{
+    bus::Bus,
+    common::Bit,
+    consts::{
+        display::{DISPLAY_SIZE_X, DISPLAY_SIZE_Y},
+        gpu::LCDC,
+        sgb::{SGB_FRAME_SIZE_X, SGB_FRAME_SIZE_Y},
+    },
+    gpu::Color,
+    save_state::{SaveState, SaveStateError, StateReader, StateWriter},
+};
+
+const PACKET_SIZE: usize = 16;
+
+/// The attributes say which palette every 8x8 cell of the screen uses
+const ATTRIBUTES_X: usize = DISPLAY_SIZE_X / 8;
+const ATTRIBUTES_Y: usize = DISPLAY_SIZE_Y / 8;
+
+/// `CHR_TRN` and `PCT_TRN` copy this many bytes from VRAM
+const TRANSFER_SIZE: usize = 4096;
+
+/// 256 tiles of 32 bytes each, `CHR_TRN` copies half of them at a time
+const BORDER_TILES_SIZE: usize = 8192;
+
+/// 32x32 tiles of 2 bytes each, only the first 28 rows are visible
+const BORDER_MAP_SIZE: usize = 2048;
+
+/// 4 palettes of 16 colors of 2 bytes each, they come right after the map in `PCT_TRN`
+const BORDER_PALETTES_SIZE: usize = 128;
+
+/// The colors the SGB starts with, before the game sends its own palettes
+const DEFAULT_PALETTE: [u16; 4] = [0x67BF, 0x265B, 0x10B5, 0x2866];
+
+pub(crate) struct Sgb {
+    /// A reset pulse was sent and the packet is not complete yet
+    is_receiving: bool,
+
+    /// Both lines went back high after the last pulse, so the next pulse is a new bit
+    is_ready_for_bit: bool,
+
+    /// How many bits of the current packet have been received
+    received_bits: u8,
+
+    packet: [u8; PACKET_SIZE],
+
+    /// The packets of the command being received, a command can be up to 7 packets long
+    command: Vec<u8>,
+
+    /// Set by `MLT_REQ`, 1, 2 or 4
+    players: u8,
+
+    /// The joypad JOYP reads from, it goes to the next one every time P15 goes high
+    current_player: u8,
+
+    /// The colors are in the RGB555 format, like on the GameBoy Color. Color 0 is shared
+    /// between all palettes
+    palettes: [[u16; 4]; 4],
+    attributes: [[u8; ATTRIBUTES_X]; ATTRIBUTES_Y],
+
+    mask: Mask,
+
+    /// `CHR_TRN` and `PCT_TRN` copy the data on the next frame
+    pending_transfer: Option<Transfer>,
+
+    border_tiles: [u8; BORDER_TILES_SIZE],
+    border_map: [u8; BORDER_MAP_SIZE],
+    border_palettes: [u8; BORDER_PALETTES_SIZE],
+
+    /// The screen of the game with the border around it, drawn at the end of every frame
+    frame: Box<[[u16; SGB_FRAME_SIZE_X]; SGB_FRAME_SIZE_Y]>,
+}
+
+/// Set by `MASK_EN`, games hide the screen while they set up the palettes and the border
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mask {
+    Cancel,
+
+    /// The screen keeps showing the last frame
+    Freeze,
+    Black,
+
+    /// The screen is filled with color 0
+    Color0,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Transfer {
+    /// `CHR_TRN` with the tiles from 0x00 to 0x7F
+    LowTiles,
+
+    /// `CHR_TRN` with the tiles from 0x80 to 0xFF
+    HighTiles,
+
+    /// `PCT_TRN`, the border map and its palettes
+    Border,
+}
+
+impl Sgb {
+    pub(crate) fn new() -> Self {
+        Self {
+            is_receiving: false,
+            is_ready_for_bit: false,
+            received_bits: 0,
+            packet: [0; PACKET_SIZE],
+            command: Vec::new(),
+            players: 1,
+            current_player: 0,
+            palettes: [DEFAULT_PALETTE; 4],
+            attributes: [[0; ATTRIBUTES_X]; ATTRIBUTES_Y],
+            mask: Mask::Cancel,
+            pending_transfer: None,
+            border_tiles: [0; BORDER_TILES_SIZE],
+            border_map: [0; BORDER_MAP_SIZE],
+            border_palettes: [0; BORDER_PALETTES_SIZE],
+            frame: Box::new([[DEFAULT_PALETTE[0]; SGB_FRAME_SIZE_X]; SGB_FRAME_SIZE_Y]),
+        }
+    }
+
+    pub(crate) fn frame(&self) -> &[[u16; SGB_FRAME_SIZE_X]; SGB_FRAME_SIZE_Y] {
+        &self.frame
+    }
+
+    /// Called every time the game writes to JOYP, only bits 4 and 5 matter
+    fn write_joyp(&mut self, previous_joyp: u8, value: u8) {
+        match value & 0b00110000 {
+            // Reset pulse, a new packet starts
+            0b00000000 => {
+                self.is_receiving = true;
+                self.is_ready_for_bit = false;
+                self.received_bits = 0;
+                self.packet = [0; PACKET_SIZE];
+            }
+
+            0b00110000 => {
+                self.is_ready_for_bit = true;
+
+                // The next joypad is selected when P15 goes high, but not while a packet
+                // is being sent
+                if !self.is_receiving && !previous_joyp.get_bit(5) {
+                    self.current_player = (self.current_player + 1) % self.players;
+                }
+            }
+
+            pulse if self.is_receiving && self.is_ready_for_bit => {
+                self.is_ready_for_bit = false;
+                self.receive_bit(pulse == 0b00010000);
+            }
+
+            _ => {}
+        }
+    }
+
+    fn receive_bit(&mut self, bit: bool) {
+        // The stop bit has to be a 0, otherwise the packet is thrown away
+        if self.received_bits as usize == PACKET_SIZE * 8 {
+            self.is_receiving = false;
+
+            if !bit {
+                self.receive_packet();
+            }
+
+            return;
+        }
+
+        let index = self.received_bits as usize;
+        self.packet[index / 8].set_bit((index % 8) as u8, bit);
+        self.received_bits += 1;
+    }
+
+    /// The number of packets is in the first one, once they all arrived the command runs
+    fn receive_packet(&mut self) {
+        if self.command.is_empty() && self.packet[0] & 0b111 == 0 {
+            return;
+        }
+
+        self.command.extend_from_slice(&self.packet);
+
+        if self.command.len() / PACKET_SIZE == (self.command[0] & 0b111) as usize {
+            let command = std::mem::take(&mut self.command);
+            self.execute_command(&command);
+        }
+    }
+
+    /// With more than one player, JOYP reads the id of the current joypad when no group
+    /// of buttons is selected, 0xF for the first one, 0xE for the second one...
+    fn get_joypad_id(&self) -> Option<u8> {
+        (self.players > 1).then_some(0x0F - self.current_player)
+    }
+
+    /// Called at the start of VBlank with the frame the GPU just finished
+    fn on_vblank(
+        &mut self,
+        screen: &[[Color; DISPLAY_SIZE_X]; DISPLAY_SIZE_Y],
+        transfer_data: Option<&[u8; TRANSFER_SIZE]>,
+    ) {
+        if let (Some(transfer), Some(data)) = (self.pending_transfer.take(), transfer_data) {
+            self.apply_transfer(transfer, data);
+        }
+
+        self.draw_frame(screen);
+    }
+}
+
+impl Bus {
+    /// Gives the JOYP write to the SGB, if there is one
+    pub(crate) fn write_joyp_to_sgb(&mut self, value: u8) {
+        if let Some(sgb) = &mut self.sgb {
+            sgb.write_joyp(self.io[0x00], value);
+        }
+    }
+
+    /// The id of the joypad selected by the SGB, `None` when only one player is connected
+    pub(crate) fn get_sgb_joypad_id(&self) -> Option<u8> {
+        self.sgb.as_ref().and_then(|sgb| sgb.get_joypad_id())
+    }
+
+    /// Only the first joypad is connected to the emulator, the others are never pressed
+    pub(crate) fn is_first_sgb_player(&self) -> bool {
+        self.sgb.as_ref().is_none_or(|sgb| sgb.current_player == 0)
+    }
+
+    /// Called by the GPU when VBlank starts
+    pub(crate) fn on_vblank_sgb(&mut self, screen: &[[Color; DISPLAY_SIZE_X]; DISPLAY_SIZE_Y]) {
+        let needs_transfer_data = match &self.sgb {
+            Some(sgb) => sgb.pending_transfer.is_some(),
+            None => return,
+        };
+
+        let transfer_data = needs_transfer_data.then(|| self.get_sgb_transfer_data());
+
+        if let Some(sgb) = &mut self.sgb {
+            sgb.on_vblank(screen, transfer_data.as_ref());
+        }
+    }
+
+    /// The SGB can't read VRAM, it reads what's on the screen. Games show the data as 256
+    /// tiles, one after the other, starting from the top left of the background
+    fn get_sgb_transfer_data(&self) -> [u8; TRANSFER_SIZE] {
+        let lcdc = self.read(LCDC);
+        let map_address: u16 = match lcdc.get_bit(3) {
+            false => 0x9800,
+            true => 0x9C00,
+        };
+
+        let mut data = [0u8; TRANSFER_SIZE];
+
+        for (i, tile) in data.chunks_exact_mut(16).enumerate() {
+            let map_offset = (i / ATTRIBUTES_X * 32 + i % ATTRIBUTES_X) as u16;
+            let tile_id = self.read_video_ram(0, map_address + map_offset);
+
+            let tile_address = match lcdc.get_bit(4) {
+                true => 0x8000 + tile_id as u16 * 16,
+                false => (0x9000 + tile_id as i8 as i32 * 16) as u16,
+            };
+
+            for (j, byte) in tile.iter_mut().enumerate() {
+                *byte = self.read_video_ram(0, tile_address + j as u16);
+            }
+        }
+
+        data
+    }
+}
+
+impl SaveState for Sgb {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.is_receiving);
+        writer.write_bool(self.is_ready_for_bit);
+        writer.write_u8(self.received_bits);
+        writer.write_bytes(&self.packet);
+        writer.write_u32(self.command.len() as u32);
+        writer.write_bytes(&self.command);
+        writer.write_u8(self.players);
+        writer.write_u8(self.current_player);
+
+        for palette in &self.palettes {
+            for color in palette {
+                writer.write_u16(*color);
+            }
+        }
+
+        for line in &self.attributes {
+            writer.write_bytes(line);
+        }
+
+        writer.write_u8(self.mask as u8);
+        writer.write_bool(self.pending_transfer.is_some());
+        writer.write_u8(self.pending_transfer.map_or(0, |transfer| transfer as u8));
+        writer.write_bytes(&self.border_tiles);
+        writer.write_bytes(&self.border_map);
+        writer.write_bytes(&self.border_palettes);
+
+        for line in self.frame.iter() {
+            for color in line {
+                writer.write_u16(*color);
+            }
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.is_receiving = reader.read_bool()?;
+        self.is_ready_for_bit = reader.read_bool()?;
+        self.received_bits = reader.read_u8()?;
+        reader.read_bytes(&mut self.packet)?;
+
+        // A command is at most 7 packets long
+        let command_size = reader.read_u32()? as usize;
+        if command_size > PACKET_SIZE * 7 {
+            return Err(SaveStateError::Corrupted);
+        }
+
+        self.command = vec![0; command_size];
+        reader.read_bytes(&mut self.command)?;
+        self.players = reader.read_u8()?;
+        self.current_player = reader.read_u8()?;
+
+        if !matches!(self.players, 1 | 2 | 4) || self.current_player >= self.players {
+            return Err(SaveStateError::Corrupted);
+        }
+
+        for palette in self.palettes.iter_mut() {
+            for color in palette.iter_mut() {
+                *color = reader.read_u16()?;
+            }
+        }
+
+        for line in self.attributes.iter_mut() {
+            reader.read_bytes(line)?;
+        }
+
+        self.mask = Mask::from_state(reader.read_u8()?)?;
+        let has_pending_transfer = reader.read_bool()?;
+        let pending_transfer = Transfer::from_state(reader.read_u8()?)?;
+        self.pending_transfer = has_pending_transfer.then_some(pending_transfer);
+        reader.read_bytes(&mut self.border_tiles)?;
+        reader.read_bytes(&mut self.border_map)?;
+        reader.read_bytes(&mut self.border_palettes)?;
+
+        for line in self.frame.iter_mut() {
+            for color in line.iter_mut() {
+                *color = reader.read_u16()?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Mask {
+    fn from_state(value: u8) -> Result<Self, SaveStateError> {
+        match value {
+            0 => Ok(Mask::Cancel),
+            1 => Ok(Mask::Freeze),
+            2 => Ok(Mask::Black),
+            3 => Ok(Mask::Color0),
+            _ => Err(SaveStateError::Corrupted),
+        }
+    }
+}
+
+impl Transfer {
+    fn from_state(value: u8) -> Result<Self, SaveStateError> {
+        match value {
+            0 => Ok(Transfer::LowTiles),
+            1 => Ok(Transfer::HighTiles),
+            2 => Ok(Transfer::Border),
+            _ => Err(SaveStateError::Corrupted),
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! The timer is built around a 16 bit counter that increments every dot, DIV is just the
+//! upper 8 bits of it. TIMA increments when the bit of the counter selected by TAC goes
+//! from 1 to 0, and it requests an interrupt when it overflows.
+//!
+//! Since TIMA looks at the counter directly, anything that makes the selected bit fall
+//! also increments it: resetting DIV, turning the timer off, or changing the frequency.
+//! The real hardware does the same thing.
+//!
+//! This is a really good resource: https://gbdev.io/pandocs/Timer_Obscure_Behaviour.html
+
+use crate::{
+    bus::Bus,
+    common::Bit,
+    consts::{
+        bus::IO_START,
+        cpu::{DIV, IF, TAC, TIMA, TMA},
+    },
+    save_state::{SaveState, SaveStateError, StateReader, StateWriter},
+};
+
+/// When TIMA overflows it isn't reloaded immediately, it stays at 0 for a cycle
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TimaReload {
+    /// Nothing is happening
+    None,
+
+    /// TIMA just overflowed and reads 0, writing to it now cancels both the reload and
+    /// the interrupt
+    Pending,
+
+    /// TIMA was reloaded with TMA during this cycle, writing to TIMA now is ignored, but
+    /// writing to TMA also changes TIMA
+    Reloading,
+}
+
+pub struct Timer {
+    /// Increments every dot, the upper 8 bits are the DIV register
+    system_counter: u16,
+
+    /// Whether the bit TIMA looks at was set the last cycle, TIMA increments when this
+    /// goes from true to false
+    last_signal: bool,
+
+    tima_reload: TimaReload,
+}
+
+impl Timer {
+    pub(crate) fn new() -> Self {
+        Self {
+            // This is what the counter is at when the boot rom hands control to the game
+            system_counter: 0xABCC,
+            last_signal: false,
+            tima_reload: TimaReload::None,
+        }
+    }
+
+    /// The timer when the GameBoy is turned on, before the boot rom runs
+    pub(crate) fn new_before_boot() -> Self {
+        Self {
+            system_counter: 0,
+            ..Self::new()
+        }
+    }
+
+    /// This needs to be called once every cycle
+    pub(crate) fn tick(&mut self, bus: &mut Bus) {
+        self.update_tima_reload(bus);
+        self.update_system_counter(bus);
+
+        let signal = self.get_signal(bus.read(TAC));
+        if self.last_signal && !signal {
+            self.increment_tima(bus);
+        }
+
+        self.last_signal = signal;
+    }
+
+    /// Handles the cycles after TIMA overflows, see `TimaReload`
+    fn update_tima_reload(&mut self, bus: &mut Bus) {
+        let was_tima_written = bus.was_tima_written;
+        bus.was_tima_written = false;
+
+        self.tima_reload = match self.tima_reload {
+            TimaReload::None => TimaReload::None,
+            TimaReload::Pending if was_tima_written => TimaReload::None,
+            TimaReload::Pending => {
+                set_tima(bus, bus.read(TMA));
+                bus.write(IF, bus.read(IF) | 0b00000100); // Enabling timer interrupt
+
+                TimaReload::Reloading
+            }
+            TimaReload::Reloading => {
+                // Whatever was written to TIMA during the reload is lost, and if TMA was
+                // written TIMA gets the new value
+                set_tima(bus, bus.read(TMA));
+
+                TimaReload::None
+            }
+        };
+    }
+
+    /// Increments the counter by a cycle, writing to DIV resets the whole counter
+    fn update_system_counter(&mut self, bus: &mut Bus) {
+        if bus.needs_to_reset_div_register {
+            self.system_counter = 0;
+            bus.needs_to_reset_div_register = false;
+        } else {
+            self.system_counter = self.system_counter.wrapping_add(4);
+        }
+
+        // DIV is written using IO directly, otherwise writing to it would reset the
+        // counter again
+        bus.io[(DIV - IO_START as u16) as usize] = (self.system_counter >> 8) as u8;
+    }
+
+    /// The bit of the counter selected by TAC, and-ed with the timer enable bit
+    fn get_signal(&self, timer_control: u8) -> bool {
+        let bit = match timer_control & 0b11 {
+            0b00 => 9, // Every 256 cycles
+            0b01 => 3, // Every 4 cycles
+            0b10 => 5, // Every 16 cycles
+            _ => 7,    // Every 64 cycles
+        };
+
+        timer_control.get_bit(2) && self.system_counter.get_bit(bit)
+    }
+
+    fn increment_tima(&mut self, bus: &mut Bus) {
+        let (result, has_overflown) = bus.read(TIMA).overflowing_add(1);
+        set_tima(bus, result);
+
+        if has_overflown {
+            self.tima_reload = TimaReload::Pending;
+        }
+    }
+}
+
+/// TIMA is written using IO directly, so the bus doesn't think the game wrote to it
+fn set_tima(bus: &mut Bus, value: u8) {
+    bus.io[(TIMA - IO_START as u16) as usize] = value;
+}
+
+impl SaveState for Timer {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.system_counter);
+        writer.write_bool(self.last_signal);
+        writer.write_u8(self.tima_reload as u8);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.system_counter = reader.read_u16()?;
+        self.last_signal = reader.read_bool()?;
+        self.tima_reload = match reader.read_u8()? {
+            0 => TimaReload::None,
+            1 => TimaReload::Pending,
+            2 => TimaReload::Reloading,
+            _ => return Err(SaveStateError::Corrupted),
+        };
+
+        Ok(())
+    }
+}